@@ -1,16 +1,39 @@
+use std::env;
 use std::fs;
 use std::ops::Range;
 
-use nom::bytes::complete::{take_until, take_while};
-use nom::character::complete::{newline, u32};
-use nom::sequence::terminated;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_until, take_until1, take_while};
+use nom::character::complete::{char, newline, u32};
+use nom::combinator::{all_consuming, map, value};
+use nom::sequence::{preceded, terminated, tuple};
 use nom::IResult;
 
 fn main() {
     println!("Let's solve AOC-03 (Part 2)!");
     let input = fs::read_to_string("aoc-03-input.txt").expect("Unable to read input");
-    let output = solve_problem(&input);
-    println!("Solution: {}", output);
+
+    // Each argument is a rule like `*:=2:product` or `#:>=3:sum`; with no arguments,
+    // we fall back to the usual gear rule.
+    let rule_args: Vec<String> = env::args().skip(1).collect();
+    let rules: Vec<GearRule> = if rule_args.is_empty() {
+        vec![GearRule::standard()]
+    } else {
+        rule_args
+            .iter()
+            .map(|arg| {
+                all_consuming(gear_rule)(arg)
+                    .map(|(_, rule)| rule)
+                    .unwrap_or_else(|_| panic!("Malformed rule: {arg}"))
+            })
+            .collect()
+    };
+
+    let (_, (parts, symbols)) = parse_input(&input).ok().unwrap();
+    for rule in rules.iter() {
+        let output = solve_problem(&parts, &symbols, rule);
+        println!("Solution ({}): {}", rule, output);
+    }
 }
 
 fn expand(range: &Range<usize>) -> Range<usize> {
@@ -32,7 +55,7 @@ fn line_to_range(line_no: usize) -> Range<usize> {
 // usize parameter is an offset which reflects the amount of input consumed
 pub enum UpdateDatum {
     Part(PartDatum, usize),
-    Gear(GearDatum, usize),
+    Symbol(SymbolDatum, usize),
     None(usize),
 }
 
@@ -44,31 +67,157 @@ pub struct PartDatum {
 }
 
 #[derive(Debug)]
-pub struct GearDatum {
+pub struct SymbolDatum {
+    symbol: char,
     adj_pos: usize,
     adj_line: usize,
 }
 
-fn solve_problem(input: &str) -> u32 {
-    let (_, (parts, gears)) = parse_input(input).ok().unwrap();
+/* ----- */
+/* Rules */
+/* ----- */
+
+// A rule picks out the symbols it cares about, says how many adjacent parts such a
+// symbol must have in order to count, and how those parts are combined into a value.
+#[derive(Debug, Clone)]
+pub struct GearRule {
+    symbols: Vec<char>,
+    arity: Arity,
+    aggregation: Aggregation,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    AtMost(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Aggregation {
+    Product,
+    Sum,
+    Min,
+    Max,
+}
+
+impl GearRule {
+    // The rule from the actual problem: `*` touching exactly two parts, multiplied.
+    fn standard() -> GearRule {
+        GearRule {
+            symbols: vec!['*'],
+            arity: Arity::Exactly(2),
+            aggregation: Aggregation::Product,
+        }
+    }
+
+    fn applies_to(&self, symbol: char) -> bool {
+        self.symbols.contains(&symbol)
+    }
+}
+
+impl Arity {
+    fn admits(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exactly(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+            Arity::AtMost(n) => count <= n,
+        }
+    }
+}
+
+impl Aggregation {
+    // Min and max have no sensible value for a symbol with no adjacent parts, so (for every
+    // aggregation, to keep them consistent) those symbols just don't contribute anything.
+    fn aggregate(&self, numbers: &[u64]) -> Option<u64> {
+        if numbers.is_empty() {
+            return None;
+        }
+        match self {
+            Aggregation::Product => Some(numbers.iter().product()),
+            Aggregation::Sum => Some(numbers.iter().sum()),
+            Aggregation::Min => numbers.iter().copied().min(),
+            Aggregation::Max => numbers.iter().copied().max(),
+        }
+    }
+}
+
+impl std::fmt::Display for GearRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbols: String = self.symbols.iter().collect();
+        let arity = match self.arity {
+            Arity::Exactly(n) => format!("={n}"),
+            Arity::AtLeast(n) => format!(">={n}"),
+            Arity::AtMost(n) => format!("<={n}"),
+        };
+        let aggregation = match self.aggregation {
+            Aggregation::Product => "product",
+            Aggregation::Sum => "sum",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+        };
+        write!(f, "{symbols}:{arity}:{aggregation}")
+    }
+}
+
+// Rules are written `<symbols>:<arity>:<aggregation>`, e.g. `*#:>=3:sum`.
+fn gear_rule(input: &str) -> IResult<&str, GearRule> {
+    map(
+        tuple((
+            terminated(take_until1(":"), char(':')),
+            terminated(arity, char(':')),
+            aggregation,
+        )),
+        |(symbols, arity, aggregation): (&str, Arity, Aggregation)| GearRule {
+            symbols: symbols.chars().collect(),
+            arity,
+            aggregation,
+        },
+    )(input)
+}
+
+fn arity(input: &str) -> IResult<&str, Arity> {
+    alt((
+        map(preceded(tag(">="), u32), |n| Arity::AtLeast(n as usize)),
+        map(preceded(tag("<="), u32), |n| Arity::AtMost(n as usize)),
+        map(preceded(tag("="), u32), |n| Arity::Exactly(n as usize)),
+    ))(input)
+}
+
+fn aggregation(input: &str) -> IResult<&str, Aggregation> {
+    alt((
+        value(Aggregation::Product, tag("product")),
+        value(Aggregation::Sum, tag("sum")),
+        value(Aggregation::Min, tag("min")),
+        value(Aggregation::Max, tag("max")),
+    ))(input)
+}
+
+/* ----- */
+/* Logic */
+/* ----- */
+
+fn solve_problem(parts: &[PartDatum], symbols: &[SymbolDatum], rule: &GearRule) -> u64 {
     let mut total = 0;
-    for g in gears.iter() {
-        let mut adj_parts: Vec<u32> = Vec::new();
-        for p in parts.iter() {
-            if p.adj_range.contains(&g.adj_pos) && p.adj_lines.contains(&g.adj_line) {
-                adj_parts.push(p.number);
-            }
+    for s in symbols.iter().filter(|s| rule.applies_to(s.symbol)) {
+        let adj_parts: Vec<u64> = parts
+            .iter()
+            .filter(|p| p.adj_range.contains(&s.adj_pos) && p.adj_lines.contains(&s.adj_line))
+            .map(|p| p.number as u64)
+            .collect();
+        if !rule.arity.admits(adj_parts.len()) {
+            continue;
         }
-        if adj_parts.len() == 2 {
-            total += adj_parts.iter().fold(1, |x, y| x * y);
+        if let Some(value) = rule.aggregation.aggregate(&adj_parts) {
+            total += value;
         }
     }
-    return total;
+    total
 }
 
-fn parse_input(input: &str) -> IResult<&str, (Vec<PartDatum>, Vec<GearDatum>)> {
+fn parse_input(input: &str) -> IResult<&str, (Vec<PartDatum>, Vec<SymbolDatum>)> {
     let mut parts: Vec<PartDatum> = Vec::new();
-    let mut gears: Vec<GearDatum> = Vec::new();
+    let mut symbols: Vec<SymbolDatum> = Vec::new();
     let mut cursor = 0;
     let mut line_no = 0;
 
@@ -86,9 +235,9 @@ fn parse_input(input: &str) -> IResult<&str, (Vec<PartDatum>, Vec<GearDatum>)> {
                 UpdateDatum::None(offset) => {
                     cursor += offset;
                 }
-                UpdateDatum::Gear(mut gear, offset) => {
-                    gear.adj_pos += cursor;
-                    gears.push(gear);
+                UpdateDatum::Symbol(mut symbol, offset) => {
+                    symbol.adj_pos += cursor;
+                    symbols.push(symbol);
                     cursor += offset;
                 }
                 UpdateDatum::Part(mut part, offset) => {
@@ -110,7 +259,7 @@ fn parse_input(input: &str) -> IResult<&str, (Vec<PartDatum>, Vec<GearDatum>)> {
         line_no += 1;
         cursor = 0;
     }
-    return Ok(("", (parts, gears)));
+    return Ok(("", (parts, symbols)));
 }
 
 // Note: the part data don't include their "expanded" diagonal range until they are absorbed
@@ -136,17 +285,17 @@ fn update_datum(input: &str, line_no: usize) -> IResult<&str, UpdateDatum> {
         };
         return Ok((rest, UpdateDatum::Part(part_datum, offset + length)));
     }
-    // Consume 1 character, returning a Gear update if it's a gear
+    // Consume 1 character, which must be a symbol since dots were already consumed;
+    // whether it acts as a gear is up to the rules
     else {
-        if rest.chars().next() == Some('*') {
-            let gear_datum = GearDatum {
-                adj_pos: offset,
-                adj_line: line_no,
-            };
-            return Ok((&rest[1..], UpdateDatum::Gear(gear_datum, offset + 1)));
-        } else {
-            return Ok((&rest[1..], UpdateDatum::None(offset + 1)));
-        }
+        let symbol = rest.chars().next().unwrap();
+        let symbol_datum = SymbolDatum {
+            symbol,
+            adj_pos: offset,
+            adj_line: line_no,
+        };
+        let len = symbol.len_utf8();
+        return Ok((&rest[len..], UpdateDatum::Symbol(symbol_datum, offset + 1)));
     }
 }
 
@@ -1,100 +1,135 @@
-use std::cell::Cell;
 use std::collections::BTreeMap;
+use std::env;
 use std::fs;
 
+use nom::branch::alt;
 use nom::bytes::complete::tag;
-use nom::character::complete::{char, newline, space0, space1, u32};
-use nom::multi::{many0, many1};
-use nom::sequence::{delimited, pair, preceded, terminated};
+use nom::character::complete::u32;
+use nom::combinator::{all_consuming, map, value, verify};
+use nom::sequence::preceded;
 use nom::IResult;
 
+// The cards, their parser and the validation pass are shared with the first part
+use aoc_04::cards::{parse_input, validate, Card};
+
 fn main() {
     println!("Let's solve AOC-04!");
     let input = fs::read_to_string("aoc-04-input.txt").expect("Unable to read file");
-    let output = solve_problem(&input);
-    println!("Solution: {}", output);
-}
 
-pub struct Card {
-    no: u32,
-    winning_numbers: Vec<u32>,
-    had_numbers: Vec<u32>,
-}
+    // The cascade rule can be given as an argument: `next` (the default), `prev`,
+    // `every=K` or `cap=N`. Passing `--strict` refuses to solve malformed input.
+    let (flags, rule_args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let strict = flags.iter().any(|flag| flag == "--strict");
+    let rule = match rule_args.first() {
+        Some(arg) => all_consuming(cascade_rule)(arg)
+            .map(|(_, rule)| rule)
+            .unwrap_or_else(|_| panic!("Malformed cascade rule: {arg}")),
+        None => CascadeRule::Next,
+    };
 
-impl Card {
-    fn value(&self) -> u32 {
-        let mut matches = 0;
-        for n in self.winning_numbers.iter() {
-            for m in self.had_numbers.iter() {
-                if n == m {
-                    matches += 1;
-                }
-            }
-        }
-        return matches;
+    let (_, cards) = parse_input(&input).ok().unwrap();
+    let diagnostics = validate(&cards);
+    for diagnostic in diagnostics.iter() {
+        println!("Warning: {}", diagnostic);
+    }
+    if strict && !diagnostics.is_empty() {
+        println!("Refusing to solve malformed input in strict mode");
+        std::process::exit(1);
     }
 
-    fn flatten(self) -> CardAbstract {
-        let vals = TreeVals {
-            value: self.value(),
-            count: 1,
-        };
-        return (self.no, Cell::new(vals));
+    let report = solve_problem(&cards, rule);
+    println!("card,copies");
+    for (no, copies) in report.copies.iter() {
+        println!("{},{}", no, copies);
     }
+    println!("Solution: {}", report.total);
 }
 
-// CardAbstract is used when we know we don't care about the actual numbers any more
-// The first u32 is the number, the second is the value
-type CardAbstract = (u32, Cell<TreeVals>);
+/* --------------- */
+/* Data Structures */
+/* --------------- */
 
-#[derive(Debug, Copy, Clone)]
-pub struct TreeVals {
-    value: u32,
-    count: u32,
+// How a card with some number of matches wins copies of other cards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CascadeRule {
+    // Copies of the next N cards (the actual problem)
+    Next,
+    // Copies of the previous N cards
+    Previous,
+    // Copies of every k-th card after this one, N of them (k is at least 1, since otherwise
+    // a card would win copies of itself)
+    EveryKth(u32),
+    // Copies of the next N cards, but never more than the cap
+    Capped(u32),
 }
 
-fn solve_problem(input: &str) -> u32 {
-    let (_, cards) = parse_input(input).ok().unwrap();
-    let card_map: BTreeMap<u32, Cell<TreeVals>> = cards.into_iter().map(|c| c.flatten()).collect();
-    let mut total_cards = 0;
-    for (k, v) in card_map.iter() {
-        println!("{:?}: {:?}", k, v);
-        total_cards += v.get().count;
-        let cards_won = v.get().value;
-        for offset in 1..(cards_won + 1) {
-            let target_key = k + offset;
-            if let Some(vals) = card_map.get(&target_key) {
-                let mut new_vals = vals.get();
-                new_vals.count += v.get().count;
-                vals.set(new_vals);
-            }
+impl CascadeRule {
+    // The card numbers won by the given card, in terms of its number of matches. Numbers
+    // that would fall outside the range of `u32` can't be cards, so they are dropped.
+    fn targets(&self, card_no: u32, matches: u32) -> Vec<u32> {
+        match *self {
+            CascadeRule::Next => (1..=matches)
+                .filter_map(|o| card_no.checked_add(o))
+                .collect(),
+            CascadeRule::Previous => (1..=matches)
+                .filter_map(|o| card_no.checked_sub(o))
+                .collect(),
+            CascadeRule::EveryKth(k) => (1..=matches)
+                .filter_map(|o| o.checked_mul(k).and_then(|d| card_no.checked_add(d)))
+                .collect(),
+            CascadeRule::Capped(cap) => (1..=matches.min(cap))
+                .filter_map(|o| card_no.checked_add(o))
+                .collect(),
         }
     }
-    return total_cards;
+
+    // Cards only ever win copies of cards on one side of them, so processing them from
+    // the other side guarantees every card has all its copies before it is scored.
+    fn backwards(&self) -> bool {
+        *self == CascadeRule::Previous
+    }
 }
 
-fn parse_input(input: &str) -> IResult<&str, Vec<Card>> {
-    many0(terminated(card, newline))(input)
+pub struct CascadeReport {
+    copies: BTreeMap<u32, u64>,
+    total: u64,
 }
 
-fn card(input: &str) -> IResult<&str, Card> {
-    // Extract the card number
-    let (rest, card_no) = delimited(pair(tag("Card"), space0), u32, char(':'))(input)?;
+/* ----- */
+/* Logic */
+/* ----- */
 
-    // Winning numbers separated by space
-    let (rest, winners) = many1(preceded(space1, u32))(rest)?;
+fn solve_problem(cards: &[Card], rule: CascadeRule) -> CascadeReport {
+    let matches: BTreeMap<u32, u32> = cards.iter().map(|c| (c.no, c.matches())).collect();
+    let mut copies: BTreeMap<u32, u64> = cards.iter().map(|c| (c.no, 1)).collect();
 
-    // Separator between winning numbers and the ones that are obtained
-    let (rest, _) = preceded(space1, char('|'))(rest)?;
+    let mut order: Vec<u32> = matches.keys().copied().collect();
+    if rule.backwards() {
+        order.reverse();
+    }
 
-    // Had numbers separated by spaces again
-    let (rest, had) = many1(preceded(space1, u32))(rest)?;
+    for no in order {
+        let held = copies[&no];
+        for target in rule.targets(no, matches[&no]) {
+            if let Some(count) = copies.get_mut(&target) {
+                *count += held;
+            }
+        }
+    }
 
-    let card = Card {
-        no: card_no,
-        winning_numbers: winners,
-        had_numbers: had,
-    };
+    let total = copies.values().sum();
+    CascadeReport { copies, total }
+}
 
-    return Ok((rest, card));
+fn cascade_rule(input: &str) -> IResult<&str, CascadeRule> {
+    alt((
+        value(CascadeRule::Next, tag("next")),
+        value(CascadeRule::Previous, tag("prev")),
+        map(
+            preceded(tag("every="), verify(u32, |k| *k > 0)),
+            CascadeRule::EveryKth,
+        ),
+        map(preceded(tag("cap="), u32), CascadeRule::Capped),
+    ))(input)
 }
@@ -0,0 +1,233 @@
+// Scratchcards, their parser and the validation pass, shared between both parts of the day
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, space0, space1, u32};
+use nom::multi::{many0, many1};
+use nom::sequence::{delimited, pair, preceded, terminated};
+use nom::IResult;
+
+/* --------------- */
+/* Data Structures */
+/* --------------- */
+
+// The numbers themselves are kept as bitsets; since those can't hold a number twice, the
+// parser also notes how long each list was as written and which numbers were repeats, so
+// that the validation pass can still see them.
+pub struct Card {
+    pub no: u32,
+    winning_numbers: NumberSet,
+    had_numbers: NumberSet,
+    listed: (usize, usize),
+    repeats: Vec<(NumberList, u32)>,
+}
+
+impl Card {
+    pub fn matches(&self) -> u32 {
+        self.winning_numbers.intersection_size(&self.had_numbers)
+    }
+}
+
+// A plain bitset over the numbers on a card; matching is then just a bitwise AND
+// followed by a popcount.
+#[derive(Debug, Clone, Default)]
+pub struct NumberSet {
+    words: Vec<u64>,
+}
+
+impl NumberSet {
+    // Returns whether the number was new to the set
+    fn insert(&mut self, n: u32) -> bool {
+        let (word, bit) = ((n / 64) as usize, n % 64);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let new = self.words[word] & (1 << bit) == 0;
+        self.words[word] |= 1 << bit;
+        new
+    }
+
+    fn intersection_size(&self, other: &NumberSet) -> u32 {
+        self.words
+            .iter()
+            .zip(other.words.iter())
+            .map(|(a, b)| (a & b).count_ones())
+            .sum()
+    }
+}
+
+/* ---------- */
+/* Validation */
+/* ---------- */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberList {
+    Winning,
+    Had,
+}
+
+// Problems with the input that would otherwise silently throw off the answer.
+#[derive(Debug, Clone)]
+pub enum Diagnostic {
+    // A number appears more than once in one of a card's lists
+    DuplicateNumber {
+        card: u32,
+        list: NumberList,
+        number: u32,
+        occurrences: usize,
+    },
+    // No cards were given with these numbers
+    MissingCards(RangeInclusive<u32>),
+    // Several cards were given with the same number
+    RepeatedCard {
+        card: u32,
+        occurrences: usize,
+    },
+    // A card's lists have different lengths from those of the first card
+    UnevenLists {
+        card: u32,
+        winning: usize,
+        had: usize,
+        expected: (usize, usize),
+    },
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::DuplicateNumber {
+                card,
+                list,
+                number,
+                occurrences,
+            } => write!(
+                f,
+                "Card {card}: {number} appears {occurrences} times in the {} numbers",
+                match list {
+                    NumberList::Winning => "winning",
+                    NumberList::Had => "had",
+                }
+            ),
+            Diagnostic::MissingCards(range) if range.start() == range.end() => {
+                write!(f, "Card {} is missing", range.start())
+            }
+            Diagnostic::MissingCards(range) => {
+                write!(f, "Cards {} to {} are missing", range.start(), range.end())
+            }
+            Diagnostic::RepeatedCard { card, occurrences } => {
+                write!(f, "Card {card} appears {occurrences} times")
+            }
+            Diagnostic::UnevenLists {
+                card,
+                winning,
+                had,
+                expected: (exp_winning, exp_had),
+            } => write!(
+                f,
+                "Card {card}: lists have lengths {winning} | {had}, expected {exp_winning} | {exp_had}"
+            ),
+        }
+    }
+}
+
+pub fn validate(cards: &[Card]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    // Numbering: cards should be exactly 1..=N, each appearing once. Nothing can come after
+    // a card numbered `u32::MAX`, so there is no next number to expect after that.
+    let mut occurrences: BTreeMap<u32, usize> = BTreeMap::new();
+    for card in cards.iter() {
+        *occurrences.entry(card.no).or_insert(0) += 1;
+    }
+    let mut expected_next = Some(1);
+    for (&no, &count) in occurrences.iter() {
+        if let Some(next) = expected_next.filter(|&next| no > next) {
+            diagnostics.push(Diagnostic::MissingCards(next..=(no - 1)));
+        }
+        if count > 1 {
+            diagnostics.push(Diagnostic::RepeatedCard {
+                card: no,
+                occurrences: count,
+            });
+        }
+        expected_next = no.checked_add(1);
+    }
+
+    // Contents of each card
+    let expected = cards.first().map(|c| c.listed);
+    for card in cards.iter() {
+        for list in [NumberList::Winning, NumberList::Had] {
+            // Every repeat is one more occurrence on top of the first
+            let mut counts: BTreeMap<u32, usize> = BTreeMap::new();
+            for &(_, n) in card.repeats.iter().filter(|&&(l, _)| l == list) {
+                *counts.entry(n).or_insert(1) += 1;
+            }
+            for (number, occurrences) in counts.into_iter() {
+                diagnostics.push(Diagnostic::DuplicateNumber {
+                    card: card.no,
+                    list,
+                    number,
+                    occurrences,
+                });
+            }
+        }
+
+        if let Some(expected) = expected.filter(|&e| e != card.listed) {
+            diagnostics.push(Diagnostic::UnevenLists {
+                card: card.no,
+                winning: card.listed.0,
+                had: card.listed.1,
+                expected,
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/* ------- */
+/* Parsers */
+/* ------- */
+
+pub fn parse_input(input: &str) -> IResult<&str, Vec<Card>> {
+    many0(terminated(card, char('\n')))(input)
+}
+
+fn card(input: &str) -> IResult<&str, Card> {
+    // Extract the card number
+    let (rest, card_no) = delimited(pair(tag("Card"), space0), u32, char(':'))(input)?;
+
+    // Winning numbers separated by space
+    let (rest, winners) = many1(preceded(space1, u32))(rest)?;
+
+    // Separator between winning numbers and the ones that are obtained
+    let (rest, _) = preceded(space1, char('|'))(rest)?;
+
+    // Had numbers separated by spaces again
+    let (rest, had) = many1(preceded(space1, u32))(rest)?;
+
+    let mut repeats = Vec::new();
+    let mut to_set = |list: NumberList, numbers: &[u32]| {
+        let mut set = NumberSet::default();
+        for &n in numbers.iter() {
+            if !set.insert(n) {
+                repeats.push((list, n));
+            }
+        }
+        set
+    };
+    let winning_numbers = to_set(NumberList::Winning, &winners);
+    let had_numbers = to_set(NumberList::Had, &had);
+
+    let card = Card {
+        no: card_no,
+        winning_numbers,
+        had_numbers,
+        listed: (winners.len(), had.len()),
+        repeats,
+    };
+
+    Ok((rest, card))
+}
@@ -0,0 +1,3 @@
+// The second part depends on this crate for the cards, so they live in a library alongside
+// the binary
+pub mod cards;
@@ -1,4 +1,3 @@
-use std::cmp::min;
 use std::fs;
 use std::ops::Range;
 
@@ -27,51 +26,57 @@ pub type CompositeFunction = Vec<FunctionPiece>;
 
 pub type SeedRange = Range<i64>;
 
-fn composite_fn(pieces: &CompositeFunction) -> impl Fn(i64) -> i64 {
-    let pieces_two = pieces.clone();
-    let f = move |x: i64| -> i64 {
-        for p in pieces_two.iter() {
-            if p.domain.contains(&x) {
-                return x + p.translation;
-            }
-        }
-        return x;
-    };
-    return f;
+// A map with its pieces sorted by domain, so that evaluating it is a binary search
+// instead of a scan. (This relies on the pieces being disjoint, as they are in the input.)
+pub struct IndexedMap {
+    pieces: CompositeFunction,
 }
 
-fn solve_problem(input: &str) -> i64 {
-    let mut min_answer = i64::MAX;
-    let (_, (seed_ranges, maps)) = parse_input(input).ok().unwrap();
-    let mut range_counter = 1;
-    for r in seed_ranges {
-        println!("Range number: {}", range_counter);
-
-        let mut seed_counter = 1;
-        for s in r.into_iter() {
-            if seed_counter % 1000 == 0 {
-                println!("Seed number: {}", seed_counter);
-            }
-            let mut cur_val = s;
-            for m in maps.iter() {
-                cur_val = composite_fn(m)(cur_val);
-            }
-            min_answer = min(min_answer, cur_val);
-            seed_counter += 1;
+impl IndexedMap {
+    fn new(pieces: &CompositeFunction) -> IndexedMap {
+        let mut pieces = pieces.clone();
+        pieces.sort_by_key(|p| p.domain.start);
+        IndexedMap { pieces }
+    }
+
+    fn eval(&self, x: i64) -> i64 {
+        let idx = self.pieces.partition_point(|p| p.domain.start <= x);
+        match idx.checked_sub(1).map(|i| &self.pieces[i]) {
+            Some(piece) if piece.domain.contains(&x) => x + piece.translation,
+            _ => x,
         }
-        range_counter += 1;
     }
-    return min_answer;
 }
 
-fn parse_input(input: &str) -> IResult<&str, (Vec<SeedRange>, Vec<CompositeFunction>)> {
-    let (rest, seed_ranges) = seed_ranges(input)?;
-    let (rest, maps) = many0(function)(rest)?;
-    return Ok((rest, (seed_ranges, maps)));
+// Reads the seed ranges off the seeds line one at a time, so they never have to be stored.
+pub struct SeedRangeStream<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for SeedRangeStream<'a> {
+    type Item = SeedRange;
+
+    fn next(&mut self) -> Option<SeedRange> {
+        let (rest, seed_range) = seed_range(self.rest).ok()?;
+        self.rest = rest;
+        Some(seed_range)
+    }
 }
 
-fn seed_ranges(input: &str) -> IResult<&str, Vec<SeedRange>> {
-    preceded(tag("seeds:"), many0(seed_range))(input)
+fn solve_problem(input: &str) -> i64 {
+    let (_, (seed_ranges, maps)) = parse_streaming(input).ok().unwrap();
+    let maps: Vec<IndexedMap> = maps.iter().map(IndexedMap::new).collect();
+    seed_ranges
+        .flatten()
+        .map(|s| maps.iter().fold(s, |val, m| m.eval(val)))
+        .min()
+        .unwrap()
+}
+
+fn parse_streaming(input: &str) -> IResult<&str, (SeedRangeStream<'_>, Vec<CompositeFunction>)> {
+    let (rest, seed_line) = preceded(tag("seeds:"), take_until("\n"))(input)?;
+    let (rest, maps) = many0(function)(rest)?;
+    Ok((rest, (SeedRangeStream { rest: seed_line }, maps)))
 }
 
 fn seed_range(input: &str) -> IResult<&str, SeedRange> {
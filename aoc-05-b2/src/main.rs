@@ -1,23 +1,91 @@
 use std::cmp::{max, min};
+use std::collections::BTreeSet;
+use std::env;
 use std::fs;
 use std::ops::Range;
+use std::time::Instant;
 
 use nom::bytes::complete::{tag, take_until};
-use nom::character::complete::{i64, newline, space0};
+use nom::character::complete::{i64, multispace0, newline, space0};
+use nom::combinator::{all_consuming, map};
 use nom::multi::many0;
-use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
 use nom::IResult;
 
 fn main() {
     println!("Let's solve AOC-05!");
+
+    // `--bench RANGES LINES` compares the indexed reduction against the old one on a
+    // synthetic almanac instead of solving the actual input.
+    let bench_args: Vec<String> = env::args().skip(1).collect();
+    if bench_args.first().map(String::as_str) == Some("--bench") {
+        let ranges = bench_args
+            .get(1)
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(1_000_000);
+        let lines = bench_args
+            .get(2)
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(100);
+        assert!(
+            ranges > 0 && lines > 0,
+            "The synthetic almanac needs at least one seed range and one line per map"
+        );
+        bench(ranges, lines);
+        return;
+    }
+
     let input = fs::read_to_string("aoc-05-input.txt").expect("Unable to read file");
-    let output = solve_problem(&input);
+    let (_, (seed_ranges, stages)) = parse_input(&input).ok().unwrap();
+    let tot_function = PiecewiseTranslation::compose_all(stages.iter().map(|s| &s.function));
+
+    // `--dump` prints the composed map back out in almanac format, and `--inverse` does
+    // the same for its inverse (if it has one); `--images` shows where each seed range ends up.
+    // `--preimage A..B` asks which seeds end up in the given location range, and
+    // `--trace SEED` follows a single seed through each of the maps.
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preimage" => {
+                let target = args
+                    .next()
+                    .and_then(|a| all_consuming(range)(&a).ok().map(|(_, r)| r))
+                    .expect("Expected a location range like 40..60 after --preimage");
+                let report = preimage_query(&target, seed_ranges.clone(), &stages);
+                print_preimage_report(&target, &report, &stages);
+            }
+            "--trace" => {
+                let seed = args
+                    .next()
+                    .and_then(|a| a.parse::<i64>().ok())
+                    .expect("Expected a seed number after --trace");
+                print!("seed {}", seed);
+                for (stage, value) in stages.iter().zip(trace(seed, &stages)) {
+                    print!(" -> {} {}", stage.destination(), value);
+                }
+                println!();
+            }
+            "--images" => {
+                for seed_range in seed_ranges.clone() {
+                    println!("{:?} -> {:?}", seed_range, tot_function.image(&seed_range));
+                }
+            }
+            "--dump" => print!("{}", tot_function.to_almanac("seed-to-location")),
+            "--inverse" => match tot_function.invert() {
+                Some(inverse) => print!("{}", inverse.to_almanac("location-to-seed")),
+                None => println!("The composed map is not invertible"),
+            },
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
+    let output = solve_problem(seed_ranges, &tot_function);
     println!("Solution: {}", output);
 }
 
 // If you are in the domain, you get moved by the translation
 // i.e. |x| x + translation
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FunctionPiece {
     domain: Range<i64>,
     translation: i64,
@@ -27,28 +95,58 @@ pub type CompositeFunction = Vec<FunctionPiece>;
 
 pub type SeedRange = Range<i64>;
 
-fn composite_fn(pieces: &CompositeFunction) -> impl Fn(i64) -> i64 {
-    let pieces_two = pieces.clone();
-    let f = move |x: i64| -> i64 {
-        for p in pieces_two.iter() {
-            if p.domain.contains(&x) {
-                return x + p.translation;
-            }
-        }
-        return x;
-    };
-    return f;
+// One of the maps in the almanac, e.g. `seed-to-soil`
+#[derive(Clone, Debug)]
+pub struct Stage {
+    name: String,
+    function: PiecewiseTranslation,
+}
+
+impl Stage {
+    // The kind of thing this stage maps to, e.g. `soil`
+    fn destination(&self) -> &str {
+        self.name.rsplit('-').next().unwrap_or(&self.name)
+    }
+}
+
+// Reads the seed ranges off the seeds line one at a time, so they never have to be stored;
+// cloning it just starts another pass over the line.
+#[derive(Clone, Debug)]
+pub struct SeedRangeStream<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for SeedRangeStream<'a> {
+    type Item = SeedRange;
+
+    fn next(&mut self) -> Option<SeedRange> {
+        let (rest, seed_range) = seed_range(self.rest).ok()?;
+        self.rest = rest;
+        Some(seed_range)
+    }
 }
 
 // Parsers
-fn parse_input(input: &str) -> IResult<&str, (Vec<SeedRange>, Vec<CompositeFunction>)> {
-    let (rest, seed_ranges) = seed_ranges(input)?;
-    let (rest, maps) = many0(function)(rest)?;
-    return Ok((rest, (seed_ranges, maps)));
+fn parse_input(input: &str) -> IResult<&str, (SeedRangeStream<'_>, Vec<Stage>)> {
+    let (rest, seed_line) = preceded(tag("seeds:"), take_until("\n"))(input)?;
+    let (rest, stages) = many0(stage)(rest)?;
+    Ok((rest, (SeedRangeStream { rest: seed_line }, stages)))
+}
+
+fn stage(input: &str) -> IResult<&str, Stage> {
+    let (rest, name) = preceded(multispace0, take_until(" map:"))(input)?;
+    let (rest, pieces) = function(rest)?;
+    let stage = Stage {
+        name: name.to_string(),
+        function: PiecewiseTranslation::normalise(&pieces),
+    };
+    Ok((rest, stage))
 }
 
-fn seed_ranges(input: &str) -> IResult<&str, Vec<SeedRange>> {
-    preceded(tag("seeds:"), many0(seed_range))(input)
+fn range(input: &str) -> IResult<&str, Range<i64>> {
+    map(separated_pair(i64, tag(".."), i64), |(start, end)| {
+        start..end
+    })(input)
 }
 
 fn seed_range(input: &str) -> IResult<&str, SeedRange> {
@@ -85,15 +183,330 @@ fn padded_i64(input: &str) -> IResult<&str, i64> {
     delimited(space0, i64, space0)(input)
 }
 
+// Piecewise translations in normal form
+//
+// The pieces are sorted, disjoint and contiguous (gaps between them are filled in with
+// explicit pieces with zero translation), adjacent pieces never share a translation, and
+// the pieces at either end never have zero translation. Outside of the pieces, the
+// function is the identity. This makes the description of each function unique.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PiecewiseTranslation {
+    pieces: CompositeFunction,
+}
+
+impl PiecewiseTranslation {
+    // Where pieces overlap, the one listed first takes priority: each piece only claims the
+    // parts of its domain that no earlier piece has already claimed.
+    //
+    // This is a single sweep over the piece boundaries in order, keeping track of which pieces
+    // cover the current stretch; the earliest-listed of them decides its translation, and
+    // stretches covered by none are the gaps.
+    fn normalise(pieces: &CompositeFunction) -> PiecewiseTranslation {
+        let mut boundaries: Vec<(i64, usize, bool)> = vec![];
+        for (idx, piece) in pieces.iter().enumerate() {
+            if !piece.domain.is_empty() {
+                boundaries.push((piece.domain.start, idx, true));
+                boundaries.push((piece.domain.end, idx, false));
+            }
+        }
+        boundaries.sort_unstable_by_key(|&(pos, _, _)| pos);
+
+        let mut covering: BTreeSet<usize> = BTreeSet::new();
+        let mut normal: CompositeFunction = vec![];
+        let mut boundaries = boundaries.into_iter().peekable();
+        while let Some(&(pos, _, _)) = boundaries.peek() {
+            while let Some((_, idx, starting)) = boundaries.next_if(|b| b.0 == pos) {
+                if starting {
+                    covering.insert(idx);
+                } else {
+                    covering.remove(&idx);
+                }
+            }
+            if let Some(&(next, _, _)) = boundaries.peek() {
+                let piece = FunctionPiece {
+                    domain: pos..next,
+                    translation: covering.first().map_or(0, |&idx| pieces[idx].translation),
+                };
+                push_merged(&mut normal, piece);
+            }
+        }
+        while normal.last().is_some_and(|p| p.translation == 0) {
+            normal.pop();
+        }
+        let leading_identity = normal.iter().take_while(|p| p.translation == 0).count();
+        normal.drain(..leading_identity);
+
+        PiecewiseTranslation { pieces: normal }
+    }
+
+    fn compose_all<'a>(
+        functions: impl IntoIterator<Item = &'a PiecewiseTranslation>,
+    ) -> PiecewiseTranslation {
+        let identity = PiecewiseTranslation { pieces: vec![] };
+        functions
+            .into_iter()
+            .fold(identity, |total, f| total.then(f))
+    }
+
+    // The composite where self is applied first and then next.
+    fn then(&self, next: &PiecewiseTranslation) -> PiecewiseTranslation {
+        PiecewiseTranslation::normalise(&compose(&self.pieces, &next.pieces))
+    }
+
+    fn eval(&self, x: i64) -> i64 {
+        let idx = self.pieces.partition_point(|p| p.domain.end <= x);
+        match self.pieces.get(idx) {
+            Some(piece) if piece.domain.contains(&x) => x + piece.translation,
+            _ => x,
+        }
+    }
+
+    // The same critical points as `crit_points`, but found by binary search, since the
+    // pieces are sorted and contiguous; the only piece boundaries that matter are those
+    // of the pieces overlapping the range.
+    fn crit_points(&self, range: Range<i64>) -> impl Iterator<Item = i64> + '_ {
+        let first = self.pieces.partition_point(|p| p.domain.end <= range.start);
+        let end = range.end;
+        let boundaries = self.pieces[first..]
+            .iter()
+            .take_while(move |p| p.domain.start < end)
+            .flat_map(|p| [p.domain.start, p.domain.end])
+            .filter(move |pt| *pt > range.start && *pt < end);
+        std::iter::once(range.start).chain(boundaries)
+    }
+
+    // The range on which the function can differ from the identity.
+    fn hull(&self) -> Range<i64> {
+        match (self.pieces.first(), self.pieces.last()) {
+            (Some(first), Some(last)) => first.domain.start..last.domain.end,
+            _ => 0..0,
+        }
+    }
+
+    // The function is the identity outside of its hull, so it is invertible exactly when
+    // the pieces inside get shuffled around within the hull without overlapping.
+    fn invert(&self) -> Option<PiecewiseTranslation> {
+        let hull = self.hull();
+        let mut images: CompositeFunction = self
+            .pieces
+            .iter()
+            .map(|p| FunctionPiece {
+                domain: translate_range(&p.domain, -p.translation),
+                translation: -p.translation,
+            })
+            .collect();
+        images.sort_by_key(|p| p.domain.start);
+
+        let in_hull = images
+            .iter()
+            .all(|p| p.domain.start >= hull.start && p.domain.end <= hull.end);
+        let disjoint = images
+            .windows(2)
+            .all(|w| w[0].domain.end <= w[1].domain.start);
+        if !in_hull || !disjoint {
+            return None;
+        }
+        Some(PiecewiseTranslation::normalise(&images))
+    }
+
+    // The image of a range, as a sorted list of disjoint ranges.
+    fn image(&self, range: &Range<i64>) -> Vec<Range<i64>> {
+        if range.is_empty() {
+            return vec![];
+        }
+        let mut images: Vec<Range<i64>> = vec![];
+        let mut remaining: Vec<Range<i64>> = vec![range.clone()];
+        for piece in self.pieces.iter() {
+            let mut leftovers: Vec<Range<i64>> = vec![];
+            for segment in remaining.iter() {
+                let (intersections, extras) = intersect(segment, &piece.domain);
+                for overlap in intersections.into_iter() {
+                    images.push(translate_range(&overlap, -piece.translation));
+                }
+                leftovers.extend(extras.into_iter().filter(|r| !r.is_empty()));
+            }
+            remaining = leftovers;
+        }
+        // Whatever is left is outside the hull, where the function is the identity
+        images.append(&mut remaining);
+        merge_ranges(images)
+    }
+
+    // Everything that gets mapped into the given range, as a sorted list of disjoint ranges.
+    fn preimage(&self, range: &Range<i64>) -> Vec<Range<i64>> {
+        let mut preimages: Vec<Range<i64>> = self
+            .pieces
+            .iter()
+            .filter_map(|piece| {
+                let (intersections, _) =
+                    intersect(&translate_range(range, piece.translation), &piece.domain);
+                intersections.into_iter().next()
+            })
+            .collect();
+        // Outside the hull, the function is the identity
+        let hull = self.hull();
+        if self.pieces.is_empty() {
+            preimages.push(range.clone());
+        } else {
+            preimages.push(range.start..min(range.end, hull.start));
+            preimages.push(max(range.start, hull.end)..range.end);
+        }
+        merge_ranges(preimages)
+    }
+
+    // Written the same way as the maps in the puzzle input; pieces with zero translation
+    // are left out, since unmapped numbers already map to themselves.
+    fn to_almanac(&self, name: &str) -> String {
+        let mut output = format!("{name} map:\n");
+        for piece in self.pieces.iter().filter(|p| p.translation != 0) {
+            output.push_str(&format!(
+                "{} {} {}\n",
+                piece.domain.start + piece.translation,
+                piece.domain.start,
+                piece.domain.end - piece.domain.start
+            ));
+        }
+        output
+    }
+}
+
+impl std::fmt::Display for PiecewiseTranslation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for piece in self.pieces.iter() {
+            writeln!(
+                f,
+                "[{}, {}) -> {:+}",
+                piece.domain.start, piece.domain.end, piece.translation
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Appends a piece, extending the last one instead if they fit together seamlessly.
+fn push_merged(pieces: &mut CompositeFunction, piece: FunctionPiece) {
+    if let Some(last) = pieces.last_mut() {
+        if last.domain.end == piece.domain.start && last.translation == piece.translation {
+            last.domain.end = piece.domain.end;
+            return;
+        }
+    }
+    pieces.push(piece);
+}
+
+// Sorts a collection of ranges and merges any that overlap or touch.
+fn merge_ranges(mut ranges: Vec<Range<i64>>) -> Vec<Range<i64>> {
+    ranges.retain(|r| !r.is_empty());
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<i64>> = vec![];
+    for range in ranges.into_iter() {
+        match merged.last_mut() {
+            Some(last) if last.end >= range.start => last.end = max(last.end, range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
 // Logical functions
-fn solve_problem(input: &str) -> i64 {
-    let (_, (seed_ranges, maps)) = parse_input(input).ok().unwrap();
-    let tot_function = compose_all(&maps);
+pub struct PreimageReport {
+    // The seeds from the input that end up in the target range
+    seeds: Vec<Range<i64>>,
+    // Where those seeds are after each stage
+    stage_ranges: Vec<Vec<Range<i64>>>,
+}
+
+// Pull the target range all the way back to the seeds, keep only the seeds we actually
+// have, and then push those forward again to see which intermediate ranges they pass through.
+fn preimage_query(
+    target: &Range<i64>,
+    seed_ranges: impl Iterator<Item = SeedRange>,
+    stages: &[Stage],
+) -> PreimageReport {
+    let mut pulled_back: Vec<Range<i64>> = vec![target.clone()];
+    for stage in stages.iter().rev() {
+        pulled_back = merge_ranges(
+            pulled_back
+                .iter()
+                .flat_map(|r| stage.function.preimage(r))
+                .collect(),
+        );
+    }
+
+    let seeds = merge_ranges(
+        seed_ranges
+            .flat_map(|seed_range| {
+                pulled_back
+                    .iter()
+                    .flat_map(|r| intersect(&seed_range, r).0)
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    );
+
+    let mut stage_ranges: Vec<Vec<Range<i64>>> = vec![];
+    let mut current = seeds.clone();
+    for stage in stages.iter() {
+        current = merge_ranges(
+            current
+                .iter()
+                .flat_map(|r| stage.function.image(r))
+                .collect(),
+        );
+        stage_ranges.push(current.clone());
+    }
+
+    PreimageReport {
+        seeds,
+        stage_ranges,
+    }
+}
+
+fn print_preimage_report(target: &Range<i64>, report: &PreimageReport, stages: &[Stage]) {
+    println!(
+        "Seeds landing in location range {:?}: {:?}",
+        target, report.seeds
+    );
+    match report.seeds.first() {
+        Some(first) => println!("Minimum such seed: {}", first.start),
+        None => println!("No seeds land in this range"),
+    }
+    for (stage, ranges) in stages.iter().zip(report.stage_ranges.iter()) {
+        println!("  via {}: {:?}", stage.destination(), ranges);
+    }
+}
+
+// The value of the given seed after each stage.
+fn trace(seed: i64, stages: &[Stage]) -> Vec<i64> {
+    stages
+        .iter()
+        .scan(seed, |value, stage| {
+            *value = stage.function.eval(*value);
+            Some(*value)
+        })
+        .collect()
+}
+
+// Only one critical point is looked at at a time, so this runs in constant memory no
+// matter how many seed ranges there are.
+fn solve_problem(
+    seed_ranges: impl Iterator<Item = SeedRange>,
+    tot_function: &PiecewiseTranslation,
+) -> i64 {
+    seed_ranges
+        .flat_map(|seed_range| tot_function.crit_points(seed_range))
+        .map(|pt| tot_function.eval(pt))
+        .min()
+        .unwrap()
+}
+
+// The original reduction, kept around to benchmark against
+fn solve_problem_unindexed(seed_ranges: &[SeedRange], tot_function: &PiecewiseTranslation) -> i64 {
     let mut output_vals: Vec<i64> = vec![];
     for seed_range in seed_ranges.iter() {
-        let crit_points = crit_points(&seed_range, &tot_function);
+        let crit_points = crit_points(seed_range, &tot_function.pieces);
         for pt in crit_points.into_iter() {
-            output_vals.push(composite_fn(&tot_function)(pt));
+            output_vals.push(tot_function.eval(pt));
         }
     }
     return output_vals.into_iter().reduce(min).unwrap();
@@ -149,85 +562,67 @@ fn intersect(chompee: &Range<i64>, chomper: &Range<i64>) -> (Vec<Range<i64>>, Ve
     }
 }
 
-// Given a partial for a function 'g' and a function 'f' defined by a vector of partials,
-// we compute the function composite as a vector of partials.
-fn expand(fn_piece: &FunctionPiece, next_pieces: &CompositeFunction) -> CompositeFunction {
-    let mut output_partials: CompositeFunction = vec![];
-    let mut to_process: Vec<Range<i64>> = vec![];
-    to_process.push(fn_piece.domain.clone());
-
-    // iterate over the next possible pieces
-    for future in next_pieces.iter() {
-        let mut leftovers: Vec<Range<i64>> = vec![];
-
-        // Iterate over remaining chunks of domain
-        for domain_piece in to_process.iter() {
-            // Compute the intersection of the domain with the inverse translated domain of the future
-            let (intersections, mut extras) = intersect(
-                domain_piece,
-                &translate_range(&future.domain, fn_piece.translation),
-            );
-
-            // When the domain gets split up, add the chunks to the leftovers
-            leftovers.append(&mut extras);
-            for domain_overlap in intersections.into_iter() {
-                let new_piece = FunctionPiece {
-                    domain: domain_overlap,
-                    translation: fn_piece.translation + future.translation,
-                };
-                output_partials.push(new_piece);
-            }
+// The pieces of a sorted, disjoint function that meet the range, cut down to it, with the gaps
+// between them filled in by pieces of zero translation, so that together they cover the range
+// exactly.
+fn covering(pieces: &[FunctionPiece], range: &Range<i64>) -> CompositeFunction {
+    let mut covered: CompositeFunction = vec![];
+    let mut cursor = range.start;
+    let first = pieces.partition_point(|p| p.domain.end <= range.start);
+    for piece in pieces[first..]
+        .iter()
+        .take_while(|p| p.domain.start < range.end)
+    {
+        let start = max(piece.domain.start, range.start);
+        let end = min(piece.domain.end, range.end);
+        if cursor < start {
+            covered.push(FunctionPiece {
+                domain: cursor..start,
+                translation: 0,
+            });
         }
-        to_process.clear();
-        to_process = leftovers;
+        covered.push(FunctionPiece {
+            domain: start..end,
+            translation: piece.translation,
+        });
+        cursor = end;
     }
-    // Process leftover blank ranges with no intersection, on which fg(x) = g(x)
-    for blank_range in to_process.into_iter() {
-        let blank_piece = FunctionPiece {
-            domain: blank_range,
-            translation: fn_piece.translation,
-        };
-        output_partials.push(blank_piece);
+    if cursor < range.end {
+        covered.push(FunctionPiece {
+            domain: cursor..range.end,
+            translation: 0,
+        });
     }
-    return output_partials;
+    covered
 }
 
-// Compute the composite of first and second (the first is applied first) as a set of linear things
+// Compute the composite of first and second (the first is applied first), which must both be
+// sorted and disjoint. Everything outside the two hulls is fixed by both, so we walk along the
+// first function's pieces over the union of the hulls in order, and split each one up by the
+// pieces of the second function that its image meets. Those are found by binary search, so
+// this takes time proportional to the size of the output (up to a logarithm); the output
+// comes out sorted and disjoint as well.
 fn compose(first: &CompositeFunction, second: &CompositeFunction) -> CompositeFunction {
-    let mut total_function: Vec<FunctionPiece> = vec![];
-
-    for first_fn in first.iter() {
-        total_function.append(&mut expand(first_fn, second));
-    }
-
-    for second_fn in second.iter() {
-        let mut remaining: Vec<Range<i64>> = vec![second_fn.domain.clone()];
-        for first_fn in first.iter() {
-            let mut all_survivors: Vec<Range<i64>> = vec![];
-            for segment in remaining.iter() {
-                let (_, mut survivors) = intersect(segment, &first_fn.domain);
-                all_survivors.append(&mut survivors);
-            }
-            remaining.clear();
-            remaining = all_survivors;
-        }
-        for default_domain in remaining.into_iter() {
-            let default_piece = FunctionPiece {
-                domain: default_domain,
-                translation: second_fn.translation,
-            };
-            total_function.push(default_piece);
-        }
-    }
-    return total_function;
-}
+    let hulls: Vec<Range<i64>> = [first, second]
+        .into_iter()
+        .filter_map(|f| Some(f.first()?.domain.start..f.last()?.domain.end))
+        .collect();
+    let Some(start) = hulls.iter().map(|h| h.start).min() else {
+        return vec![];
+    };
+    let end = hulls.iter().map(|h| h.end).max().unwrap();
 
-fn compose_all(functions: &Vec<CompositeFunction>) -> CompositeFunction {
     let mut total_function: CompositeFunction = vec![];
-    for f in functions.iter() {
-        total_function = compose(&total_function, f);
+    for first_fn in covering(first, &(start..end)) {
+        let image = translate_range(&first_fn.domain, -first_fn.translation);
+        for second_fn in covering(second, &image) {
+            total_function.push(FunctionPiece {
+                domain: translate_range(&second_fn.domain, first_fn.translation),
+                translation: first_fn.translation + second_fn.translation,
+            });
+        }
     }
-    return total_function;
+    total_function
 }
 
 /// Given a function and a domain range, output a vector of all of the
@@ -247,3 +642,66 @@ fn crit_points(range: &Range<i64>, func: &CompositeFunction) -> Vec<i64> {
     }
     return crit_points;
 }
+
+/* ------------ */
+/* Benchmarking */
+/* ------------ */
+
+fn bench(ranges: usize, lines: usize) {
+    let input = synthetic_almanac(ranges, lines);
+    println!(
+        "Synthetic almanac: {} seed ranges, {} lines per map",
+        ranges, lines
+    );
+
+    let now = Instant::now();
+    let (_, (seed_ranges, stages)) = parse_input(&input).ok().unwrap();
+    let tot_function = PiecewiseTranslation::compose_all(stages.iter().map(|s| &s.function));
+    println!(
+        "Composed into {} pieces in {:?}",
+        tot_function.pieces.len(),
+        now.elapsed()
+    );
+
+    let now = Instant::now();
+    let indexed = solve_problem(seed_ranges.clone(), &tot_function);
+    println!("Indexed: {} in {:?}", indexed, now.elapsed());
+
+    let now = Instant::now();
+    let collected: Vec<SeedRange> = seed_ranges.collect();
+    let unindexed = solve_problem_unindexed(&collected, &tot_function);
+    println!("Unindexed: {} in {:?}", unindexed, now.elapsed());
+
+    assert_eq!(indexed, unindexed, "The two pipelines disagree");
+}
+
+// Seven maps, each shuffling disjoint windows of [0, 10^9) around by random offsets,
+// using a little xorshift generator so that runs are reproducible.
+fn synthetic_almanac(ranges: usize, lines: usize) -> String {
+    const SPAN: u64 = 1_000_000_000;
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut random = move |bound: u64| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state % bound
+    };
+
+    let mut input = String::from("seeds:");
+    for _ in 0..ranges {
+        input.push_str(&format!(" {} {}", random(SPAN), 1 + random(SPAN / 1000)));
+    }
+    input.push('\n');
+
+    let window = SPAN / lines as u64;
+    for stage in 0..7 {
+        input.push_str(&format!("\nstage-{}-to-stage-{} map:\n", stage, stage + 1));
+        for line in 0..lines as u64 {
+            let length = 1 + random(window);
+            let source = line * window;
+            let dest = random(SPAN);
+            input.push_str(&format!("{} {} {}\n", dest, source, length));
+        }
+    }
+    input
+}
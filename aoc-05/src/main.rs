@@ -1,4 +1,3 @@
-use std::cmp::min;
 use std::fs;
 use std::ops::Range;
 
@@ -27,40 +26,56 @@ pub type CompositeFunction = Vec<FunctionPiece>;
 
 pub type Seed = i64;
 
-fn composite_fn(pieces: &CompositeFunction) -> impl Fn(i64) -> i64 {
-    let pieces_two = pieces.clone();
-    let f = move |x: i64| -> i64 {
-        for p in pieces_two.iter() {
-            if p.domain.contains(&x) {
-                return x + p.translation;
-            }
-        }
-        return x;
-    };
-    return f;
+// A map with its pieces sorted by domain, so that evaluating it is a binary search
+// instead of a scan. (This relies on the pieces being disjoint, as they are in the input.)
+pub struct IndexedMap {
+    pieces: CompositeFunction,
 }
 
-fn solve_problem(input: &str) -> i64 {
-    let mut results: Vec<i64> = Vec::new();
-    let (_, (seeds, maps)) = parse_input(input).ok().unwrap();
-    for s in seeds {
-        let mut cur_val = s;
-        for m in maps.iter() {
-            cur_val = composite_fn(m)(cur_val);
+impl IndexedMap {
+    fn new(pieces: &CompositeFunction) -> IndexedMap {
+        let mut pieces = pieces.clone();
+        pieces.sort_by_key(|p| p.domain.start);
+        IndexedMap { pieces }
+    }
+
+    fn eval(&self, x: i64) -> i64 {
+        let idx = self.pieces.partition_point(|p| p.domain.start <= x);
+        match idx.checked_sub(1).map(|i| &self.pieces[i]) {
+            Some(piece) if piece.domain.contains(&x) => x + piece.translation,
+            _ => x,
         }
-        results.push(cur_val);
     }
-    return *results.iter().reduce(min).unwrap();
 }
 
-fn parse_input(input: &str) -> IResult<&str, (Vec<Seed>, Vec<CompositeFunction>)> {
-    let (rest, seeds) = seeds(input)?;
-    let (rest, maps) = many0(function)(rest)?;
-    return Ok((rest, (seeds, maps)));
+// Reads the seeds off the seeds line one at a time, so they never have to be stored.
+pub struct SeedStream<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for SeedStream<'a> {
+    type Item = Seed;
+
+    fn next(&mut self) -> Option<Seed> {
+        let (rest, seed) = preceded(space1::<&str, ()>, i64)(self.rest).ok()?;
+        self.rest = rest;
+        Some(seed)
+    }
 }
 
-fn seeds(input: &str) -> IResult<&str, Vec<Seed>> {
-    preceded(tag("seeds:"), many0(preceded(space1, i64)))(input)
+fn solve_problem(input: &str) -> i64 {
+    let (_, (seeds, maps)) = parse_streaming(input).ok().unwrap();
+    let maps: Vec<IndexedMap> = maps.iter().map(IndexedMap::new).collect();
+    seeds
+        .map(|s| maps.iter().fold(s, |val, m| m.eval(val)))
+        .min()
+        .unwrap()
+}
+
+fn parse_streaming(input: &str) -> IResult<&str, (SeedStream<'_>, Vec<CompositeFunction>)> {
+    let (rest, seed_line) = preceded(tag("seeds:"), take_until("\n"))(input)?;
+    let (rest, maps) = many0(function)(rest)?;
+    Ok((rest, (SeedStream { rest: seed_line }, maps)))
 }
 
 fn function(input: &str) -> IResult<&str, CompositeFunction> {
@@ -1,71 +1,285 @@
 use nom::{
     bytes::complete::tag,
-    character::complete::{char, digit1, space0, u64},
+    character::complete::{char, digit1, space0},
     multi::many0,
     sequence::delimited,
     IResult,
 };
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+use std::cmp::{max, min};
+use std::env;
 use std::fs;
+use std::iter::zip;
+use std::ops::RangeInclusive;
 
 fn main() {
-    println!("Let's solve AOC-06!");
+    println!("Let's solve AOC-06 (Part 2)!");
     let input = fs::read_to_string("aoc-06-input.txt").expect("Unable to read file");
-    let output = solve_problem(&input);
+
+    // By default, the numbers on each line are kerned together into one race, as in
+    // Part 2; `--separate` reads them as separate races instead, as in Part 1.
+    // `--model linear|capped=N|rate=K|cubic` picks the race physics (K can be a fraction
+    // like `3/2`), and `--verify` checks the exact winning intervals against brute force.
+    let mut mode = ParseMode::Kerned;
+    let mut model: Box<dyn RaceModel> = Box::new(LinearCharge);
+    let mut verify = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--separate" => mode = ParseMode::Separate,
+            "--verify" => verify = true,
+            "--model" => {
+                let spec = args.next().expect("Expected a model after --model");
+                model = race_model(&spec).unwrap_or_else(|| panic!("Unknown model: {spec}"));
+            }
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
+    let (_, boat_races) = boat_races(&input, mode).ok().unwrap();
+    let mut output = BigInt::one();
+    for race in boat_races.iter() {
+        let interval = model.winning_interval(race);
+        if verify {
+            verify_interval(model.as_ref(), race, &interval);
+        }
+        let count = interval_size(&interval);
+        match &interval {
+            Some(holds) => println!(
+                "Race {:?}: hold for {} to {} ({} ways)",
+                race,
+                holds.start(),
+                holds.end(),
+                count
+            ),
+            None => println!("Race {:?}: no way to win", race),
+        }
+        output *= count;
+    }
     println!("Solution: {}", output);
 }
 
 // Data structures
-type BoatRace = (u64, u64);
+type BoatRace = (BigInt, BigInt);
+
+// The hold times that beat the record, if there are any
+type WinningInterval = Option<RangeInclusive<BigInt>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    Separate,
+    Kerned,
+}
+
+// How far the boat goes in a race, given how long the button was held. Every model here
+// has the boat going further the longer you hold up to some point and less far after it,
+// so the winning hold times always form an interval. (Distances are rational so that
+// fractional rates compare exactly against the record.)
+pub trait RaceModel {
+    fn distance(&self, hold: &BigInt, time: &BigInt) -> BigRational;
+
+    // The winning interval computed directly, for models that know how
+    fn exact_interval(&self, _race: &BoatRace) -> Option<WinningInterval> {
+        None
+    }
+
+    fn winning_interval(&self, race: &BoatRace) -> WinningInterval {
+        self.exact_interval(race)
+            .unwrap_or_else(|| brute_force_interval(self, race))
+    }
+}
+
+// The actual problem: speed equals hold time
+pub struct LinearCharge;
+
+// Speed goes up with hold time, but only up to the cap
+pub struct CappedCharge {
+    cap: BigInt,
+}
+
+// Speed goes up by a fixed (possibly fractional) rate per unit of hold time
+pub struct ChargeRate {
+    rate: BigRational,
+}
+
+// Speed goes up with the square of the hold time. The distance is then a cubic in the hold
+// time, with no neat formula for where it crosses the record, so this one is brute-forced.
+pub struct CubicCharge;
+
+impl RaceModel for LinearCharge {
+    fn distance(&self, hold: &BigInt, time: &BigInt) -> BigRational {
+        BigRational::from_integer(hold * (time - hold))
+    }
+
+    fn exact_interval(&self, race: &BoatRace) -> Option<WinningInterval> {
+        let (time, distance) = race;
+        Some(quadratic_interval(&BigInt::one(), time, distance))
+    }
+}
+
+impl RaceModel for CappedCharge {
+    fn distance(&self, hold: &BigInt, time: &BigInt) -> BigRational {
+        BigRational::from_integer(min(hold, &self.cap) * (time - hold))
+    }
+
+    // Below the cap, this is the linear charge model; above it, the distance falls off
+    // linearly, and we win while cap * (time - hold) > distance.
+    fn exact_interval(&self, race: &BoatRace) -> Option<WinningInterval> {
+        let (time, distance) = race;
+        if self.cap <= BigInt::zero() {
+            return Some(None);
+        }
+        let below_cap = quadratic_interval(&BigInt::one(), time, distance)
+            .map(|holds| holds.start().clone()..=min(holds.end(), &self.cap).clone())
+            .filter(|holds| holds.start() <= holds.end());
+        let above_cap = Some(self.cap.clone()..=(time - distance / &self.cap - 1))
+            .filter(|holds| holds.start() <= holds.end());
+
+        // Both pieces agree at the cap, so they join up whenever they're both nonempty
+        let interval = match (below_cap, above_cap) {
+            (None, None) => None,
+            (Some(holds), None) | (None, Some(holds)) => Some(holds),
+            (Some(below), Some(above)) => Some(below.start().clone()..=above.end().clone()),
+        };
+        Some(interval)
+    }
+}
+
+impl RaceModel for ChargeRate {
+    fn distance(&self, hold: &BigInt, time: &BigInt) -> BigRational {
+        &self.rate * BigRational::from_integer(hold * (time - hold))
+    }
+
+    // With rate p / q, we win when p * a * (time - a) > q * distance
+    fn exact_interval(&self, race: &BoatRace) -> Option<WinningInterval> {
+        let (time, distance) = race;
+        if !self.rate.is_positive() {
+            return Some(None);
+        }
+        Some(quadratic_interval(
+            self.rate.numer(),
+            time,
+            &(distance * self.rate.denom()),
+        ))
+    }
+}
+
+impl RaceModel for CubicCharge {
+    fn distance(&self, hold: &BigInt, time: &BigInt) -> BigRational {
+        BigRational::from_integer(hold * hold * (time - hold))
+    }
+}
+
+fn race_model(spec: &str) -> Option<Box<dyn RaceModel>> {
+    if spec == "linear" {
+        return Some(Box::new(LinearCharge));
+    }
+    if let Some(cap) = spec.strip_prefix("capped=") {
+        return Some(Box::new(CappedCharge {
+            cap: cap.parse().ok()?,
+        }));
+    }
+    if spec == "cubic" {
+        return Some(Box::new(CubicCharge));
+    }
+    if let Some(rate) = spec.strip_prefix("rate=") {
+        return Some(Box::new(ChargeRate {
+            rate: rate.parse().ok()?,
+        }));
+    }
+    None
+}
 
 // Actual solution
-fn solve_problem(input: &str) -> u64 {
-    let (_, boat_race) = boat_race(input).ok().unwrap();
-    return num_solutions(&boat_race);
-}
-
-fn num_solutions(boat_race: &BoatRace) -> u64 {
-    let (time, distance) = boat_race;
-    let mut first_success = time / 2;
-    for a in 1..=(time / 2) {
-        if a * (time - a) > *distance {
-            first_success = a;
-            break;
+
+// We win when rate * a * (time - a) > distance, i.e. rate * a^2 - rate * time * a + distance < 0,
+// so the winning hold times lie strictly between the roots of that quadratic. The integer
+// square root of its discriminant gets us to within one of the smallest winner, and then
+// we just check its neighbours directly; this handles the case where the record is exactly
+// reachable, since then the roots themselves are not winners.
+fn quadratic_interval(rate: &BigInt, time: &BigInt, distance: &BigInt) -> WinningInterval {
+    let wins = |a: &BigInt| rate * a * (time - a) > *distance;
+
+    let discriminant: BigInt = rate * rate * time * time - 4 * rate * distance;
+    if discriminant.sign() == num_bigint::Sign::Minus {
+        return None;
+    }
+
+    let mut first_success: BigInt = (rate * time - discriminant.sqrt()) / (2 * rate);
+    first_success = max(first_success, BigInt::zero());
+    while first_success > BigInt::zero() && wins(&(&first_success - 1)) {
+        first_success -= 1;
+    }
+    while first_success <= time / 2 && !wins(&first_success) {
+        first_success += 1;
+    }
+    if first_success > time / 2 {
+        return None;
+    }
+
+    // The winners are symmetric about time / 2
+    let last_success = time - &first_success;
+    Some(first_success..=last_success)
+}
+
+// Tries every hold time, and checks that the winners really do form an interval.
+fn brute_force_interval<M: RaceModel + ?Sized>(model: &M, race: &BoatRace) -> WinningInterval {
+    let (time, distance) = race;
+    let mut interval: WinningInterval = None;
+    let mut hold = BigInt::zero();
+    while hold <= *time {
+        if model.distance(&hold, time) > BigRational::from_integer(distance.clone()) {
+            interval = match interval {
+                None => Some(hold.clone()..=hold.clone()),
+                Some(holds) if holds.end() + 1 == hold => {
+                    Some(holds.start().clone()..=hold.clone())
+                }
+                Some(_) => panic!("The winning hold times for {:?} are not an interval", race),
+            };
         }
+        hold += 1;
     }
-    let sol_count = if time % 2 == 0 {
-        ((time / 2) - first_success) * 2 + 1
-    } else {
-        ((time / 2) - first_success + 1) * 2
-    };
-    return sol_count;
+    interval
 }
 
-// Parsers
-fn boat_race(input: &str) -> IResult<&str, BoatRace> {
-    let (rest, time) = time(input)?;
-    let (rest, distance) = distance(rest)?;
-    let race = (time, distance);
-    return Ok((rest, race));
+fn verify_interval(model: &dyn RaceModel, race: &BoatRace, interval: &WinningInterval) {
+    let expected = brute_force_interval(model, race);
+    if *interval != expected {
+        panic!(
+            "Race {:?}: computed {:?} but brute force gives {:?}",
+            race, interval, expected
+        );
+    }
 }
 
-fn time(input: &str) -> IResult<&str, u64> {
-    delimited(tag("Time:"), u64_spaced_out, char('\n'))(input)
+fn interval_size(interval: &WinningInterval) -> BigInt {
+    match interval {
+        Some(holds) => holds.end() - holds.start() + 1,
+        None => BigInt::zero(),
+    }
 }
 
-fn distance(input: &str) -> IResult<&str, u64> {
-    delimited(tag("Distance:"), u64_spaced_out, char('\n'))(input)
+// Parsers
+fn boat_races(input: &str, mode: ParseMode) -> IResult<&str, Vec<BoatRace>> {
+    let (rest, times) = delimited(tag("Time:"), many0(nonspace_padded), char('\n'))(input)?;
+    let (rest, distances) = delimited(tag("Distance:"), many0(nonspace_padded), char('\n'))(rest)?;
+
+    let races: Vec<BoatRace> = match mode {
+        ParseMode::Separate => zip(times.iter(), distances.iter())
+            .map(|(time, distance)| (to_bigint(&[time]), to_bigint(&[distance])))
+            .collect(),
+        ParseMode::Kerned => vec![(to_bigint(&times), to_bigint(&distances))],
+    };
+    Ok((rest, races))
 }
 
 fn nonspace_padded(input: &str) -> IResult<&str, &str> {
     delimited(space0, digit1, space0)(input)
 }
 
-fn u64_spaced_out(input: &str) -> IResult<&str, u64> {
-    let (rest, pieces) = many0(nonspace_padded)(input)?;
-    let mut stripped_str = String::new();
-    for s in pieces.iter() {
-        stripped_str.push_str(s);
-    }
-    let (_, val) = u64::<&str, ()>(stripped_str.as_str()).ok().unwrap();
-    return Ok((rest, val));
+// Reads the digits of all the given pieces, one after another, as a single number
+fn to_bigint(pieces: &[&str]) -> BigInt {
+    pieces.concat().parse().unwrap()
 }
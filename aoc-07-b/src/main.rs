@@ -1,55 +1,90 @@
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till1};
+use nom::character::complete::{multispace0, newline, space0, u8};
+use nom::combinator::{all_consuming, map, value, verify};
+use nom::multi::many0;
+use nom::sequence::{delimited, pair, preceded, terminated};
+use nom::IResult;
 use regex::Regex;
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
+use std::env;
 use std::fs;
 
 fn main() {
     println!("Let's solve AOC-07!");
     let input = fs::read_to_string("aoc-07-input.txt").expect("Unable to read input");
-    let solution = solve_problem(&input);
+
+    // The rules can be `jokers` (the default, as in Part 2), `jacks` (as in Part 1), or
+    // the path to a rules file.
+    let rules = match env::args().nth(1).as_deref() {
+        None | Some("jokers") => Rules::jokers(),
+        Some("jacks") => Rules::jacks(),
+        Some(path) => {
+            let rules_input = fs::read_to_string(path).expect("Unable to read rules file");
+            let (_, rules) = all_consuming(rules_file)(&rules_input).expect("Malformed rules file");
+            rules
+        }
+    };
+
+    let solution = solve_problem(&input, &rules);
     println!("Solution: {}", solution);
 }
 
 // Data
+// A card is just its position in the rules' ranking, from lowest to highest
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-enum Card {
-    Joker,
-    Two,
-    Three,
-    Four,
-    Five,
-    Six,
-    Seven,
-    Eight,
-    Nine,
-    Ten,
-    Queen,
-    King,
-    Ace,
+struct Card(u8);
+
+// The type of a hand is determined by how many cards there are of each rank, sorted from
+// largest to smallest group; comparing these lexicographically gives the usual order
+// (e.g. [3, 2] is a full house, which beats [3, 1, 1], three of a kind) for any size of hand.
+//
+// Straights and flushes don't fit this pattern, so they borrow the counts of the hand they
+// beat and are bumped above it: straights and flushes go just above three of a kind, as in
+// poker, and straight flushes just above one-less-than-all of a kind.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+struct HandType {
+    counts: Vec<u8>,
+    special: Special,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-enum HandType {
-    HighCard,
-    OnePair,
-    TwoPair,
-    ThreeOfAKind,
-    FullHouse,
-    FourOfAKind,
-    FiveOfAKind,
+enum Special {
+    None,
+    Straight,
+    Flush,
+    StraightFlush,
+}
+
+impl HandType {
+    fn from_counts(counts: Vec<u8>) -> HandType {
+        HandType {
+            counts,
+            special: Special::None,
+        }
+    }
+
+    // A hand with the given counts, which is bumped above hands with exactly those counts
+    fn above(counts: Vec<u8>, special: Special) -> HandType {
+        HandType { counts, special }
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+// The tie-break key is worked out from the rules ahead of time, so that hands can be
+// compared without them.
+#[derive(Clone, PartialEq, Eq)]
 struct Hand {
-    cards: [Card; 5],
+    cards: Vec<Card>,
     hand_type: HandType,
+    tie_break: Vec<Card>,
 }
 
 impl Ord for Hand {
     fn cmp(&self, other: &Self) -> Ordering {
         let hands_cmp = self.hand_type.cmp(&other.hand_type);
         match hands_cmp {
-            Ordering::Equal => self.cards.cmp(&other.cards),
+            Ordering::Equal => self.tie_break.cmp(&other.tie_break),
             _ => hands_cmp,
         }
     }
@@ -61,10 +96,117 @@ impl PartialOrd for Hand {
     }
 }
 
+// Rules
+#[derive(Clone, Debug)]
+struct Rules {
+    // Card labels from lowest to highest
+    ranking: Vec<char>,
+    // Card labels that are wild
+    wild: Vec<char>,
+    wild_upgrade: WildUpgrade,
+    tie_break: TieBreak,
+    hand_size: usize,
+    // Suit labels; if there are any, each card is written as its label followed by its suit
+    suits: Vec<char>,
+    straights: bool,
+    flushes: bool,
+}
+
+// How wildcards affect the type of a hand
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WildUpgrade {
+    // Wildcards join the largest group of other cards, which is always the best choice
+    Best,
+    // Wildcards can't match anything, not even each other
+    Dead,
+}
+
+// How hands of the same type are compared
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TieBreak {
+    // Card by card, from the first (the actual problem)
+    LeftToRight,
+    // Card by card, from the last
+    RightToLeft,
+    // Like poker: the largest groups first, higher cards first within groups of the same size
+    Grouped,
+}
+
+impl Rules {
+    fn jacks() -> Rules {
+        Rules {
+            ranking: "23456789TJQKA".chars().collect(),
+            wild: vec![],
+            wild_upgrade: WildUpgrade::Best,
+            tie_break: TieBreak::LeftToRight,
+            hand_size: 5,
+            suits: vec![],
+            straights: false,
+            flushes: false,
+        }
+    }
+
+    fn jokers() -> Rules {
+        Rules {
+            ranking: "J23456789TQKA".chars().collect(),
+            wild: vec!['J'],
+            wild_upgrade: WildUpgrade::Best,
+            tie_break: TieBreak::LeftToRight,
+            hand_size: 5,
+            suits: vec![],
+            straights: false,
+            flushes: false,
+        }
+    }
+
+    fn card(&self, c: char) -> Option<Card> {
+        self.ranking
+            .iter()
+            .position(|&label| label == c)
+            .map(|rank| Card(rank as u8))
+    }
+
+    fn is_wild(&self, card: Card) -> bool {
+        self.wild.contains(&self.ranking[card.0 as usize])
+    }
+
+    fn hand(&self, cards: Vec<Card>, suits: Vec<char>) -> Hand {
+        Hand {
+            hand_type: get_hand_type(&cards, &suits, self),
+            tie_break: self.tie_break_key(&cards),
+            cards,
+        }
+    }
+
+    fn tie_break_key(&self, cards: &[Card]) -> Vec<Card> {
+        match self.tie_break {
+            TieBreak::LeftToRight => cards.to_vec(),
+            TieBreak::RightToLeft => cards.iter().rev().copied().collect(),
+            TieBreak::Grouped => {
+                let multiplicities = multiplicities(cards);
+                let mut key = cards.to_vec();
+                key.sort_by_key(|card| std::cmp::Reverse((multiplicities[card], *card)));
+                key
+            }
+        }
+    }
+}
+
 // Parsing
-fn parse_input(input: &str) -> Vec<(Hand, u64)> {
+fn parse_input(input: &str, rules: &Rules) -> Vec<(Hand, u64)> {
     let mut output: Vec<(Hand, u64)> = vec![];
-    let re = Regex::new(r"^(?<cards>(A|K|Q|J|T|9|8|7|6|5|4|3|2){5})\s(?<bid>\d+)$").unwrap();
+    let labels: String = rules.ranking.iter().collect();
+    let suits: String = rules.suits.iter().collect();
+    let card_pattern = if suits.is_empty() {
+        format!("[{}]", regex::escape(&labels))
+    } else {
+        format!("[{}][{}]", regex::escape(&labels), regex::escape(&suits))
+    };
+    let re = Regex::new(&format!(
+        r"^(?<cards>(?:{}){{{}}})\s(?<bid>\d+)$",
+        card_pattern, rules.hand_size
+    ))
+    .unwrap();
     let lines = input.split('\n');
 
     for line in lines.into_iter() {
@@ -75,27 +217,130 @@ fn parse_input(input: &str) -> Vec<(Hand, u64)> {
 
         let cards_str = &caps["cards"];
         let card_vec: Vec<char> = cards_str.chars().collect();
-        let card_arr: [char; 5] = card_vec.try_into().ok().unwrap();
-        let cards = card_arr.map(|c| char_to_card(&c).unwrap());
-        let hand_type = get_hand_type(&cards);
+        let (cards, suits): (Vec<Card>, Vec<char>) = if rules.suits.is_empty() {
+            let cards = card_vec.iter().map(|c| rules.card(*c).unwrap()).collect();
+            (cards, vec![])
+        } else {
+            card_vec
+                .chunks(2)
+                .map(|pair| (rules.card(pair[0]).unwrap(), pair[1]))
+                .unzip()
+        };
 
         let bid_str = &caps["bid"];
         let bid: u64 = bid_str.parse().ok().unwrap();
 
-        let hand = Hand {
-            cards: cards,
-            hand_type: hand_type,
-        };
-        output.push((hand, bid));
+        output.push((rules.hand(cards, suits), bid));
     }
 
     return output;
 }
 
+// A rules file looks like this, one setting per line:
+//   ranking: J23456789TQKA
+//   wild: J
+//   wild-upgrade: best
+//   tie-break: left-to-right
+//   hand-size: 5
+//   suits: SHDC
+//   straights: yes
+//   flushes: yes
+// Any settings left out are taken from the usual rules for Part 1.
+//
+// Straights and flushes are ranked against three of a kind and all-but-one of a kind, which
+// only make sense (and come in the right order) with at least five cards, so rules that ask
+// for them with smaller hands don't parse.
+fn rules_file(input: &str) -> IResult<&str, Rules> {
+    verify(
+        map(
+            many0(delimited(multispace0, setting, multispace0)),
+            rules_from_settings,
+        ),
+        |rules: &Rules| rules.hand_size >= 5 || !(rules.straights || rules.flushes),
+    )(input)
+}
+
+fn rules_from_settings(settings: Vec<Setting>) -> Rules {
+    let mut rules = Rules::jacks();
+    for setting in settings.into_iter() {
+        match setting {
+            Setting::Ranking(ranking) => rules.ranking = ranking,
+            Setting::Wild(wild) => rules.wild = wild,
+            Setting::WildUpgrade(upgrade) => rules.wild_upgrade = upgrade,
+            Setting::TieBreak(tie_break) => rules.tie_break = tie_break,
+            Setting::HandSize(size) => rules.hand_size = size as usize,
+            Setting::Suits(suits) => rules.suits = suits,
+            Setting::Straights(enabled) => rules.straights = enabled,
+            Setting::Flushes(enabled) => rules.flushes = enabled,
+        }
+    }
+    rules
+}
+
+enum Setting {
+    Ranking(Vec<char>),
+    Wild(Vec<char>),
+    WildUpgrade(WildUpgrade),
+    TieBreak(TieBreak),
+    HandSize(u8),
+    Suits(Vec<char>),
+    Straights(bool),
+    Flushes(bool),
+}
+
+fn setting(input: &str) -> IResult<&str, Setting> {
+    terminated(
+        alt((
+            map(preceded(key("ranking"), labels), Setting::Ranking),
+            map(preceded(key("wild"), labels), Setting::Wild),
+            map(
+                preceded(
+                    key("wild-upgrade"),
+                    alt((
+                        value(WildUpgrade::Best, tag("best")),
+                        value(WildUpgrade::Dead, tag("dead")),
+                    )),
+                ),
+                Setting::WildUpgrade,
+            ),
+            map(
+                preceded(
+                    key("tie-break"),
+                    alt((
+                        value(TieBreak::LeftToRight, tag("left-to-right")),
+                        value(TieBreak::RightToLeft, tag("right-to-left")),
+                        value(TieBreak::Grouped, tag("grouped")),
+                    )),
+                ),
+                Setting::TieBreak,
+            ),
+            map(preceded(key("hand-size"), u8), Setting::HandSize),
+            map(preceded(key("suits"), labels), Setting::Suits),
+            map(preceded(key("straights"), flag), Setting::Straights),
+            map(preceded(key("flushes"), flag), Setting::Flushes),
+        )),
+        pair(space0, newline),
+    )(input)
+}
+
+fn key<'a>(name: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, ()> {
+    value((), pair(tag(name), pair(tag(":"), space0)))
+}
+
+fn flag(input: &str) -> IResult<&str, bool> {
+    alt((value(true, tag("yes")), value(false, tag("no"))))(input)
+}
+
+fn labels(input: &str) -> IResult<&str, Vec<char>> {
+    map(take_till1(char::is_whitespace), |s: &str| {
+        s.chars().collect()
+    })(input)
+}
+
 // Non-parsing functions
-fn solve_problem(input: &str) -> u64 {
-    let mut hands_and_bids = parse_input(input);
-    hands_and_bids.sort_by_key(proj);
+fn solve_problem(input: &str, rules: &Rules) -> u64 {
+    let mut hands_and_bids = parse_input(input, rules);
+    hands_and_bids.sort_by(|(h1, _), (h2, _)| h1.cmp(h2));
 
     let mut rank = 1;
     let mut total = 0;
@@ -106,69 +351,134 @@ fn solve_problem(input: &str) -> u64 {
     return total;
 }
 
-fn get_hand_type(cards: &[Card; 5]) -> HandType {
-    // Let's write a real algorithm instead of just case-checking
-    let cards_copy = *cards;
+fn multiplicities(cards: &[Card]) -> BTreeMap<Card, u8> {
     let mut multiplicities_map: BTreeMap<Card, u8> = BTreeMap::new();
-    for card in cards_copy.iter() {
+    for card in cards.iter() {
         multiplicities_map
             .entry(*card)
             .and_modify(|c| *c += 1)
             .or_insert(1);
     }
-    let mut mults_max: [u8; 2] = [0; 2];
+    multiplicities_map
+}
+
+fn get_hand_type(cards: &[Card], suits: &[char], rules: &Rules) -> HandType {
+    let multiplicities_map = multiplicities(cards);
+    let mut counts: Vec<u8> = vec![];
     let mut jokers = 0;
-    for key in multiplicities_map.keys() {
-        if *key == Card::Joker {
-            if let Some(val) = multiplicities_map.get(key) {
-                jokers = *val;
-            }
+    for (key, val) in multiplicities_map.iter() {
+        if rules.is_wild(*key) {
+            jokers += *val;
         } else {
-            let val = multiplicities_map.get(key).unwrap();
-            let major = mults_max[0]; // (The only one that might move)
-            if *val > mults_max[0] {
-                mults_max[0] = *val;
-                mults_max[1] = major;
-            } else if *val > mults_max[1] {
-                mults_max[1] = *val;
-            }
+            counts.push(*val);
         }
     }
+    counts.sort_by(|a, b| b.cmp(a));
 
-    mults_max[0] += jokers;
+    match rules.wild_upgrade {
+        // Jokers join the largest group (or make up a group of their own if there are no others)
+        WildUpgrade::Best => match counts.first_mut() {
+            Some(largest) => *largest += jokers,
+            None => counts.push(jokers),
+        },
+        // Each dead card is a group of its own
+        WildUpgrade::Dead => counts.extend(std::iter::repeat_n(1, jokers as usize)),
+    }
+
+    let straight = rules.straights && is_straight(cards, rules);
+    let flush = rules.flushes && is_flush(cards, suits, rules);
 
-    match mults_max {
-        [5, 0] => HandType::FiveOfAKind,
-        [4, 1] => HandType::FourOfAKind,
-        [3, 2] => HandType::FullHouse,
-        [3, 1] => HandType::ThreeOfAKind,
-        [2, 2] => HandType::TwoPair,
-        [2, 1] => HandType::OnePair,
-        [1, 1] => HandType::HighCard,
-        _ => HandType::HighCard,
+    let mut candidates = vec![HandType::from_counts(counts)];
+    if straight && flush {
+        candidates.push(HandType::above(
+            all_but_one(cards.len()),
+            Special::StraightFlush,
+        ));
+    }
+    if flush {
+        candidates.push(HandType::above(
+            three_of_a_kind(cards.len()),
+            Special::Flush,
+        ));
     }
+    if straight {
+        candidates.push(HandType::above(
+            three_of_a_kind(cards.len()),
+            Special::Straight,
+        ));
+    }
+    candidates.into_iter().max().unwrap()
+}
+
+// The shapes that straights and flushes are ranked just above. The rules only allow those
+// with at least five cards, so these are always genuine hands, below four of a kind.
+fn three_of_a_kind(hand_size: usize) -> Vec<u8> {
+    std::iter::once(3)
+        .chain(std::iter::repeat_n(1, hand_size - 3))
+        .collect()
+}
+
+fn all_but_one(hand_size: usize) -> Vec<u8> {
+    vec![hand_size as u8 - 1, 1]
+}
+
+// The ranks in the hand are consecutive (according to the ranking), with wildcards
+// allowed to fill any gaps if they are able to.
+fn is_straight(cards: &[Card], rules: &Rules) -> bool {
+    let (wild, mut fixed): (Vec<Card>, Vec<Card>) = cards
+        .iter()
+        .partition(|card| rules.is_wild(**card) && rules.wild_upgrade == WildUpgrade::Best);
+    fixed.sort();
+    let distinct = fixed.windows(2).all(|w| w[0] != w[1]);
+    let span = match (fixed.first(), fixed.last()) {
+        (Some(low), Some(high)) => (high.0 - low.0) as usize + 1,
+        _ => 0,
+    };
+    distinct && span <= fixed.len() + wild.len() && cards.len() > 1
 }
 
-fn char_to_card(c: &char) -> Option<Card> {
-    match c {
-        'A' => Some(Card::Ace),
-        'K' => Some(Card::King),
-        'Q' => Some(Card::Queen),
-        'J' => Some(Card::Joker),
-        'T' => Some(Card::Ten),
-        '9' => Some(Card::Nine),
-        '8' => Some(Card::Eight),
-        '7' => Some(Card::Seven),
-        '6' => Some(Card::Six),
-        '5' => Some(Card::Five),
-        '4' => Some(Card::Four),
-        '3' => Some(Card::Three),
-        '2' => Some(Card::Two),
-        _ => None,
+// Every card has the same suit, with wildcards taking whichever suit they need to.
+fn is_flush(cards: &[Card], suits: &[char], rules: &Rules) -> bool {
+    if suits.is_empty() {
+        return false;
+    }
+    let mut fixed_suits = cards
+        .iter()
+        .zip(suits.iter())
+        .filter(|(card, _)| !(rules.is_wild(**card) && rules.wild_upgrade == WildUpgrade::Best))
+        .map(|(_, suit)| suit);
+    match fixed_suits.next() {
+        Some(first) => fixed_suits.all(|suit| suit == first),
+        None => true,
     }
 }
 
-fn proj(vals: &(Hand, u64)) -> Hand {
-    let (h, _) = vals;
-    return *h;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(text: &str) -> Rules {
+        all_consuming(rules_file)(text)
+            .expect("Malformed rules file")
+            .1
+    }
+
+    #[test]
+    fn small_hands() {
+        let one_card = rules("hand-size: 1\n");
+        assert_eq!(solve_problem("A 10\n2 20\n", &one_card), 20 + 2 * 10);
+
+        let two_cards = rules("hand-size: 2\n");
+        assert_eq!(
+            solve_problem("AA 5\nKA 7\n23 11\n", &two_cards),
+            11 + 2 * 7 + 3 * 5
+        );
+    }
+
+    #[test]
+    fn straights_need_five_cards() {
+        assert!(all_consuming(rules_file)("hand-size: 2\nstraights: yes\n").is_err());
+        assert!(all_consuming(rules_file)("hand-size: 4\nflushes: yes\n").is_err());
+        assert!(all_consuming(rules_file)("hand-size: 5\nstraights: yes\n").is_ok());
+    }
 }
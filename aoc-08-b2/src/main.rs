@@ -1,8 +1,8 @@
-use std::cmp::min;
-use std::collections::BTreeMap;
-use std::{fs, time::Instant};
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
+use std::fmt;
+use std::{env, fs, time::Instant};
 
-use num::integer::lcm;
+use num::integer::{ExtendedGcd, Integer};
 
 use nom::{
     branch::alt,
@@ -18,6 +18,17 @@ fn main() {
     let now = Instant::now();
     println!("Let's solve AOC-08!");
     let input = fs::read_to_string("aoc-08-input.txt").expect("Unable to read file");
+
+    // Any of `--reachable`, `--scc`, `--unreachable-z`, `--dead-ends` or `--dot PATH`
+    // analyses the graph instead of solving the problem.
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        let (_, (directions, symbol_graph)) =
+            problem_input(&input).expect("Failed to parse problem input");
+        analyse(&args, &directions, &symbol_graph);
+        return;
+    }
+
     let solution = solve_problem(&input);
     println!("Solution: {}", solution);
     println!("Time elapsed: {:?}", now.elapsed());
@@ -39,6 +50,22 @@ struct Symbol(char, char, char);
 #[derive(Debug)]
 struct SymbolGraph(BTreeMap<Symbol, (Symbol, Symbol)>);
 
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Symbol(c1, c2, c3) = self;
+        write!(f, "{c1}{c2}{c3}")
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Left => write!(f, "L"),
+            Direction::Right => write!(f, "R"),
+        }
+    }
+}
+
 /* ------- */
 /* Parsers */
 /* ------- */
@@ -82,6 +109,36 @@ fn symbol(input: &str) -> IResult<&str, Symbol> {
     })(input)
 }
 
+// Everything we need to know about the walk from one starting symbol: after `prefix` steps
+// it enters a loop of length `period`. It is at a symbol ending in 'Z' at each of the
+// `early_zs` times (before the loop starts) and at each of the `loop_zs` times (during the
+// first lap of the loop), and therefore also every `period` steps after each of the latter.
+#[derive(Debug, Clone)]
+struct GhostCycle {
+    prefix: usize,
+    period: usize,
+    early_zs: Vec<usize>,
+    loop_zs: Vec<usize>,
+}
+
+impl GhostCycle {
+    fn is_z_time(&self, time: usize) -> bool {
+        if time < self.prefix {
+            self.early_zs.contains(&time)
+        } else {
+            let lap_time = self.prefix + (time - self.prefix) % self.period;
+            self.loop_zs.contains(&lap_time)
+        }
+    }
+}
+
+// The set of times congruent to one of the residues modulo the modulus
+#[derive(Debug, Clone)]
+struct Congruences {
+    modulus: i128,
+    residues: Vec<i128>,
+}
+
 /* ----- */
 /* Logic */
 /* ----- */
@@ -90,157 +147,118 @@ fn solve_problem(input: &str) -> i128 {
     let (_, (directions, symbol_graph)) =
         problem_input(input).expect("Failed to parse problem input");
     let SymbolGraph(graph) = &symbol_graph;
-    let total_directions = directions.len();
-    let total_symbols = graph.keys().len();
     let starting_symbols: Vec<&Symbol> = graph.keys().filter(|s| ends_in_A(*s)).collect();
 
-    // Start by proccessing every future that starts with the symbols ending in 'A' until we reach a point where we know
-    // they must have looped for purely mathematical reasons (literally the pigeonhole principle)
-    let mut processed_futures: Vec<(usize, Vec<usize>)> = vec![];
-    for start in starting_symbols.iter() {
-        let (period, z_history) = get_future(
-            **start,
-            &directions,
-            &symbol_graph,
-            total_directions * total_symbols,
-            total_directions,
+    // Start by following every symbol ending in 'A' until the state (symbol, position on
+    // the tape) repeats, at which point we know everything about its future.
+    let cycles: Vec<GhostCycle> = starting_symbols
+        .iter()
+        .map(|start| get_cycle(**start, &directions, &symbol_graph))
+        .collect();
+    for cycle in cycles.iter() {
+        println!(
+            "Prefix: {}, Period: {}, Early Zs: {:?}, Loop Zs: {:?}",
+            cycle.prefix, cycle.period, cycle.early_zs, cycle.loop_zs
         );
-        println!("Period: {:?}, Z-history: {:?}", period, z_history);
-        processed_futures.push((period, z_history));
     }
 
-    // Next, compute any universal overlap between Z-histories; if we find one, we are done.
-    let just_z_histories: Vec<Vec<usize>> = processed_futures
-        .clone()
-        .into_iter()
-        .map(|(_, y)| y)
-        .collect();
-    let overlap = just_z_histories
-        .into_iter()
-        .reduce(|x, y| vec_intersect(&x, &y))
-        .expect("Failed to find any histories");
-    println!("Overlap of all Z-histories: {:?}", overlap);
-    if !overlap.is_empty() {
-        return overlap.into_iter().reduce(min).unwrap() as i128;
-    }
-    // The first common intersection lies beyond the horizon!
-    else {
-        let asymptotics_data: Vec<(i128, Vec<i128>)> = processed_futures
-            .iter()
-            .map(|x| get_asymptotics(x.clone(), total_directions * total_symbols))
-            .collect();
-        let (_total_period, intersected_asymp) = asymptotics_data
-            .into_iter()
-            .reduce(|x, y| intersect_asymptotics(&x, &y))
-            .unwrap();
-        let first_intersection = intersected_asymp.iter().reduce(min).unwrap();
-        return first_intersection + (total_directions * total_symbols) as i128;
+    // Any time before every walk has entered its loop is an early Z-time of the walk with
+    // the longest prefix, so we can just check those directly.
+    let latest = cycles
+        .iter()
+        .max_by_key(|c| c.prefix)
+        .expect("No starting symbols");
+    if let Some(time) = latest
+        .early_zs
+        .iter()
+        .find(|time| cycles.iter().all(|c| c.is_z_time(**time)))
+    {
+        return *time as i128;
     }
-}
 
-fn vec_intersect<T>(vec1: &Vec<T>, vec2: &Vec<T>) -> Vec<T>
-where
-    T: PartialEq + Clone,
-{
-    let mut common: Vec<T> = vec![];
-    for val1 in vec1.iter() {
-        for val2 in vec2.iter() {
-            if val1 == val2 {
-                common.push(val1.clone())
-            }
-        }
-    }
-    return common;
+    // Otherwise, every walk is in its loop, and the answer is the first time from then on
+    // that satisfies one of each walk's congruences at once.
+    let combined = cycles
+        .iter()
+        .map(|c| Congruences {
+            modulus: c.period as i128,
+            residues: c.loop_zs.iter().map(|t| *t as i128).collect(),
+        })
+        .reduce(|x, y| intersect_congruences(&x, &y))
+        .unwrap();
+    println!(
+        "Combined period: {}, Residues: {:?}",
+        combined.modulus, combined.residues
+    );
+    let earliest = latest.prefix as i128;
+    combined
+        .residues
+        .iter()
+        .map(|r| earliest + (r - earliest).rem_euclid(combined.modulus))
+        .min()
+        .expect("The walks never all reach Z-symbols at the same time")
 }
 
-fn get_asymptotics(future: (usize, Vec<usize>), sample_size: usize) -> (i128, Vec<i128>) {
-    let mut z_asymptotics: Vec<i128> = vec![];
-    let (period, z_history) = future;
-    for step in z_history.iter() {
-        let step = *step as i128;
-        let sample_size = sample_size as i128;
-        let period = period as i128;
-        let translated_step = step - &sample_size + period;
-        if (0..period).contains(&translated_step) {
-            z_asymptotics.push(translated_step);
-        }
-    }
-    return (period as i128, z_asymptotics);
-}
-
-// Almost all of the compute time is spent on this function
-fn intersect_asymptotics(
-    asymp1: &(i128, Vec<i128>),
-    asymp2: &(i128, Vec<i128>),
-) -> (i128, Vec<i128>) {
-    let (period1, z_asymp1) = asymp1;
-    let (period2, z_asymp2) = asymp2;
-    let period = lcm(*period1, *period2);
-    let div1 = period / period1;
-    let div2 = period / period2;
-    let mut asymp: Vec<i128> = vec![];
-    for val1 in z_asymp1.iter() {
-        for val2 in z_asymp2.iter() {
-            for mult1 in 0..div1 {
-                for mult2 in 0..div2 {
-                    if val1 + (mult1 * period1) == val2 + (mult2 * period2) {
-                        asymp.push(val1 + (mult1 * period1));
-                    }
-                }
+// The generalised Chinese Remainder Theorem: x = a (mod m) and x = b (mod n) have a common
+// solution exactly when gcd(m, n) divides b - a, in which case the solutions form a single
+// class modulo lcm(m, n). With several residues on each side, we just try every pair.
+fn intersect_congruences(first: &Congruences, second: &Congruences) -> Congruences {
+    let (m, n) = (first.modulus, second.modulus);
+    let ExtendedGcd {
+        gcd, x: m_coeff, ..
+    } = m.extended_gcd(&n);
+    let modulus = m / gcd * n;
+
+    let mut residues: Vec<i128> = vec![];
+    for a in first.residues.iter() {
+        for b in second.residues.iter() {
+            let diff = b - a;
+            if diff % gcd != 0 {
+                continue;
             }
+            // m * m_coeff = gcd (mod n), so stepping a by this many multiples of m gets to b
+            let steps = (diff / gcd * m_coeff).rem_euclid(n / gcd);
+            residues.push((a + m * steps).rem_euclid(modulus));
         }
     }
-    println!("Period: {:?}, Z-asymptotics: {:?}", period, asymp);
-    return (period, asymp);
+    residues.sort();
+    residues.dedup();
+    Congruences { modulus, residues }
 }
 
-fn get_future(
-    starting_symbol: Symbol,
-    directions: &Vec<Direction>,
-    graph: &SymbolGraph,
-    limit: usize,
-    tape_size: usize,
-) -> (usize, Vec<usize>) {
+fn get_cycle(starting_symbol: Symbol, directions: &[Direction], graph: &SymbolGraph) -> GhostCycle {
+    let tape_size = directions.len();
     let mut current_symbol = starting_symbol;
 
-    // Steps taken after the starting position
-    let mut step_counter: usize = 0;
+    // The first time each (symbol, tape position) state was seen
+    let mut first_seen: BTreeMap<(Symbol, usize), usize> = BTreeMap::new();
 
-    // The entire history up to `limit`
-    let mut history: Vec<(Symbol, usize)> = vec![(starting_symbol, 0)];
-
-    // The locations of symbols ending in Z within the history
+    // Every time (so far) at which we were on a symbol ending in Z
     let mut z_history: Vec<usize> = vec![];
 
-    for d in directions.iter().cycle() {
-        // Take one step
-        current_symbol = next_step(&graph, &current_symbol, d).expect("Failed to find symbol");
-        step_counter += 1;
-
-        history.push((current_symbol, step_counter % tape_size));
-
-        // Add valid ending positions to the `z_history`
-        if ends_in_Z(&current_symbol) {
-            z_history.push(step_counter)
+    let mut step_counter: usize = 0;
+    loop {
+        let state = (current_symbol, step_counter % tape_size);
+        if let Some(prefix) = first_seen.get(&state) {
+            let prefix = *prefix;
+            let (early_zs, loop_zs) = z_history.into_iter().partition(|t| *t < prefix);
+            return GhostCycle {
+                prefix,
+                period: step_counter - prefix,
+                early_zs,
+                loop_zs,
+            };
         }
-        if step_counter == limit {
-            break;
+        first_seen.insert(state, step_counter);
+        if ends_in_Z(&current_symbol) {
+            z_history.push(step_counter);
         }
-    }
 
-    // Now, using this, let's find the asymptotic period of our guy (which is necessarily at least `tape_size`)
-    let final_state = history.pop();
-    let mut current_state;
-    let mut steps_backward: usize = 0;
-    loop {
-        current_state = history.pop();
-        steps_backward += 1;
-        if current_state == final_state {
-            break;
-        }
+        // Take one step
+        let d = &directions[step_counter % tape_size];
+        current_symbol = next_step(graph, &current_symbol, d).expect("Failed to find symbol");
+        step_counter += 1;
     }
-
-    return (steps_backward, z_history);
 }
 
 fn next_step(graph: &SymbolGraph, current: &Symbol, direction: &Direction) -> Option<Symbol> {
@@ -270,3 +288,233 @@ fn ends_in_Z(symbol: &Symbol) -> bool {
         _ => false,
     }
 }
+
+/* -------- */
+/* Analysis */
+/* -------- */
+
+fn analyse(args: &[String], directions: &[Direction], symbol_graph: &SymbolGraph) {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--reachable" => {
+                for (start, reachable) in reachable_from_starts(directions, symbol_graph) {
+                    let nodes = symbols_of(&reachable);
+                    println!(
+                        "{} reaches {} nodes (in {} states): {}",
+                        start,
+                        nodes.len(),
+                        reachable.len(),
+                        nodes.join(" ")
+                    );
+                }
+            }
+            "--scc" => {
+                for component in strongly_connected_components(directions, symbol_graph) {
+                    let nodes = symbols_of(&component);
+                    println!(
+                        "Component of {} states, through {} nodes: {}",
+                        component.len(),
+                        nodes.len(),
+                        nodes.join(" ")
+                    );
+                }
+            }
+            "--unreachable-z" => {
+                let nodes: Vec<String> = unreachable_z_symbols(directions, symbol_graph)
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect();
+                println!("Unreachable Z-symbols: {}", nodes.join(" "));
+            }
+            "--dead-ends" => {
+                for (from, direction, to) in dead_ends(symbol_graph) {
+                    println!("{} goes {} to undefined symbol {}", from, direction, to);
+                }
+            }
+            "--dot" => {
+                let path = args.next().expect("Expected a path after --dot");
+                fs::write(path, to_dot(symbol_graph)).expect("Unable to write file");
+                println!("Wrote graph to {}", path);
+            }
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+}
+
+// A state of the walk: the symbol we are on and our position on the direction tape
+type State = (Symbol, usize);
+
+// The tape decides which edge is taken, so every state has exactly one successor (or none,
+// if the symbol is never defined)
+fn successor(state: &State, directions: &[Direction], symbol_graph: &SymbolGraph) -> Option<State> {
+    let (symbol, position) = state;
+    let direction = directions.get(*position)?;
+    let next = next_step(symbol_graph, symbol, direction)?;
+    Some((next, (position + 1) % directions.len()))
+}
+
+// Starting at the beginning of the tape, the walk just runs until it repeats a state
+fn reachable_from(
+    start: Symbol,
+    directions: &[Direction],
+    symbol_graph: &SymbolGraph,
+) -> BTreeSet<State> {
+    let mut reachable: BTreeSet<State> = BTreeSet::from([(start, 0)]);
+    let mut current = (start, 0);
+    while let Some(next) = successor(&current, directions, symbol_graph) {
+        if !reachable.insert(next) {
+            break;
+        }
+        current = next;
+    }
+    reachable
+}
+
+fn reachable_from_starts(
+    directions: &[Direction],
+    symbol_graph: &SymbolGraph,
+) -> Vec<(Symbol, BTreeSet<State>)> {
+    let SymbolGraph(graph) = symbol_graph;
+    graph
+        .keys()
+        .filter(|s| ends_in_A(s))
+        .map(|start| (*start, reachable_from(*start, directions, symbol_graph)))
+        .collect()
+}
+
+fn unreachable_z_symbols(directions: &[Direction], symbol_graph: &SymbolGraph) -> Vec<Symbol> {
+    let SymbolGraph(graph) = symbol_graph;
+    let reachable: BTreeSet<Symbol> = reachable_from_starts(directions, symbol_graph)
+        .into_iter()
+        .flat_map(|(_, reachable)| reachable.into_iter().map(|(symbol, _)| symbol))
+        .collect();
+    graph
+        .keys()
+        .filter(|s| ends_in_Z(s) && !reachable.contains(s))
+        .copied()
+        .collect()
+}
+
+// Edges pointing at symbols that never get a line of their own
+fn dead_ends(symbol_graph: &SymbolGraph) -> Vec<(Symbol, Direction, Symbol)> {
+    let SymbolGraph(graph) = symbol_graph;
+    let mut dead_ends = vec![];
+    for (from, (left, right)) in graph.iter() {
+        for (direction, to) in [(Direction::Left, left), (Direction::Right, right)] {
+            if !graph.contains_key(to) {
+                dead_ends.push((*from, direction, *to));
+            }
+        }
+    }
+    dead_ends
+}
+
+// Tarjan's algorithm over the states of the walk. Only the components that contain a cycle
+// are returned, largest first. There can be a lot of states (one for each symbol and tape
+// position), so instead of recursing, we keep an explicit stack of the states being visited,
+// each with a flag for whether its successor has been dealt with yet.
+fn strongly_connected_components(
+    directions: &[Direction],
+    symbol_graph: &SymbolGraph,
+) -> Vec<Vec<State>> {
+    let SymbolGraph(graph) = symbol_graph;
+    let mut counter = 0;
+    let mut index: BTreeMap<State, usize> = BTreeMap::new();
+    let mut low_link: BTreeMap<State, usize> = BTreeMap::new();
+    let mut stack: Vec<State> = vec![];
+    let mut on_stack: BTreeSet<State> = BTreeSet::new();
+    let mut components: Vec<Vec<State>> = vec![];
+
+    let states = graph
+        .keys()
+        .flat_map(|symbol| (0..directions.len()).map(move |position| (*symbol, position)));
+    for root in states {
+        if index.contains_key(&root) {
+            continue;
+        }
+        let mut call_stack: Vec<(State, bool)> = vec![(root, false)];
+        while let Some(&(state, expanded)) = call_stack.last() {
+            if !expanded {
+                if let Entry::Vacant(entry) = index.entry(state) {
+                    entry.insert(counter);
+                    low_link.insert(state, counter);
+                    counter += 1;
+                    stack.push(state);
+                    on_stack.insert(state);
+                }
+                call_stack.last_mut().unwrap().1 = true;
+                if let Some(next) = successor(&state, directions, symbol_graph) {
+                    if !index.contains_key(&next) {
+                        call_stack.push((next, false));
+                        continue;
+                    } else if on_stack.contains(&next) {
+                        let low = low_link[&state].min(index[&next]);
+                        low_link.insert(state, low);
+                    }
+                }
+            }
+
+            // Everything reachable from this state has been visited
+            call_stack.pop();
+            if low_link[&state] == index[&state] {
+                let mut component = vec![];
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack.remove(&member);
+                    component.push(member);
+                    if member == state {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+            if let Some(&(parent, _)) = call_stack.last() {
+                let low = low_link[&parent].min(low_link[&state]);
+                low_link.insert(parent, low);
+            }
+        }
+    }
+
+    let mut components: Vec<Vec<State>> = components
+        .into_iter()
+        .filter(|c| c.len() > 1 || successor(&c[0], directions, symbol_graph) == Some(c[0]))
+        .map(|mut c| {
+            c.sort();
+            c
+        })
+        .collect();
+    components.sort_by_key(|c| std::cmp::Reverse(c.len()));
+    components
+}
+
+// The distinct symbols that a collection of states passes through
+fn symbols_of<'a>(states: impl IntoIterator<Item = &'a State>) -> Vec<String> {
+    let symbols: BTreeSet<Symbol> = states.into_iter().map(|(symbol, _)| *symbol).collect();
+    symbols.iter().map(|s| s.to_string()).collect()
+}
+
+fn to_dot(symbol_graph: &SymbolGraph) -> String {
+    let SymbolGraph(graph) = symbol_graph;
+    let mut output = String::from("digraph network {\n");
+    for symbol in graph.keys().filter(|s| ends_in_A(s) || ends_in_Z(s)) {
+        let shape = if ends_in_A(symbol) {
+            "box"
+        } else {
+            "doublecircle"
+        };
+        output.push_str(&format!("    \"{}\" [shape={}];\n", symbol, shape));
+    }
+    for (from, (left, right)) in graph.iter() {
+        output.push_str(&format!(
+            "    \"{}\" -> \"{}\" [label=\"L\"];\n",
+            from, left
+        ));
+        output.push_str(&format!(
+            "    \"{}\" -> \"{}\" [label=\"R\"];\n",
+            from, right
+        ));
+    }
+    output.push_str("}\n");
+    output
+}
@@ -4,15 +4,97 @@ use nom::{
     sequence::{delimited, terminated},
     IResult,
 };
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+use std::env;
 use std::fs;
 
 fn main() {
     println!("Let's solve AOC-09!");
     let input = fs::read_to_string("aoc-09-input.txt").expect("Unable to read file");
-    let solution = solve_problem(&input);
+
+    // `--at INDEX` evaluates the fitted polynomials at some other index (counting the first
+    // value of each sequence as index 0) instead of just before the start.
+    let mut args = env::args().skip(1);
+    let mut index: Option<BigInt> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--at" => {
+                let value = args.next().expect("Expected an index after --at");
+                index = Some(value.parse().expect("Malformed index"));
+            }
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
+    let solution = solve_problem(&input, index);
     println!("Solution: {}", solution);
 }
 
+/* --------------- */
+/* Data Structures */
+/* --------------- */
+
+// The polynomial through a sequence, stored in Newton form: the leading entry of each row
+// of the difference table, so that p(x) = newton[0] + newton[1] * C(x, 1) + newton[2] * C(x, 2) + ...
+// where the values of the sequence are p(0), p(1), and so on.
+#[derive(Debug, Clone)]
+struct Fit {
+    newton: Vec<BigInt>,
+}
+
+#[derive(Debug, Clone)]
+enum FitError {
+    // The difference table ran out of values before reaching a row of zeros, so nothing
+    // pins down the polynomial's degree
+    NeverZero,
+    Empty,
+}
+
+impl Fit {
+    // C(x, k) for integer x (even negative), built up term by term; each division is exact
+    fn eval(&self, x: &BigInt) -> BigInt {
+        let mut total = BigInt::zero();
+        let mut binomial = BigInt::one();
+        for (k, coefficient) in self.newton.iter().enumerate() {
+            if k > 0 {
+                binomial = binomial * (x - (k - 1)) / k;
+            }
+            total += coefficient * &binomial;
+        }
+        total
+    }
+
+    fn degree(&self) -> usize {
+        self.newton.iter().rposition(|c| !c.is_zero()).unwrap_or(0)
+    }
+
+    // The coefficients in the usual basis 1, x, x^2, ..., found by expanding each C(x, k)
+    fn coefficients(&self) -> Vec<BigRational> {
+        let mut coefficients = vec![BigRational::zero(); self.degree() + 1];
+        let mut binomial: Vec<BigRational> = vec![BigRational::one()];
+        for (k, newton) in self.newton.iter().enumerate().take(self.degree() + 1) {
+            if k > 0 {
+                // Multiply by (x - (k - 1)) / k
+                let shift = BigRational::from_integer(BigInt::from(k - 1));
+                let scale = BigRational::from_integer(BigInt::from(k));
+                let mut next = vec![BigRational::zero(); binomial.len() + 1];
+                for (i, b) in binomial.iter().enumerate() {
+                    next[i + 1] += b / &scale;
+                    next[i] -= b * &shift / &scale;
+                }
+                binomial = next;
+            }
+            let newton = BigRational::from_integer(newton.clone());
+            for (i, b) in binomial.iter().enumerate() {
+                coefficients[i] += &newton * b;
+            }
+        }
+        coefficients
+    }
+}
+
 /* ------- */
 /* Parsers */
 /* ------- */
@@ -33,54 +115,56 @@ fn padded_i64(input: &str) -> IResult<&str, i64> {
 /* Logic */
 /* ----- */
 
-fn solve_problem(input: &str) -> i64 {
+// Predicts the value at the given index for each sequence (by default, the one just before
+// its start) and adds them up; sequences that can't be fitted are reported and skipped.
+fn solve_problem(input: &str, index: Option<BigInt>) -> BigInt {
     let (_, seqs) = problem_input(input).expect("Failed to parse problem input");
-    let predictions: Vec<i64> = seqs.iter().map(predict).collect();
-    let total = predictions.iter().fold(0, |x, y| x + y);
-    return total;
-}
-
-fn predict(seq: &Vec<i64>) -> i64 {
-    // Start by generating the list of 'derivative' sequences
-    let start_seq = seq.clone();
-    let mut last_seq: Vec<i64> = start_seq.clone();
-    let mut diff_seqs: Vec<Vec<i64>> = vec![start_seq];
-    loop {
-        let next_diff = differences(&last_seq);
-        last_seq = next_diff.clone();
-        diff_seqs.push(next_diff);
-        if is_all_zero(&last_seq) || last_seq.is_empty() {
-            break;
+    let mut total = BigInt::zero();
+    for (i, seq) in seqs.iter().enumerate() {
+        let at = index.clone().unwrap_or_else(|| BigInt::from(-1));
+        match fit(seq) {
+            Ok(fit) => {
+                let prediction = fit.eval(&at);
+                let coefficients: Vec<String> =
+                    fit.coefficients().iter().map(|c| c.to_string()).collect();
+                println!(
+                    "Sequence {}: degree {}, coefficients [{}], p({}) = {}",
+                    i,
+                    fit.degree(),
+                    coefficients.join(", "),
+                    at,
+                    prediction
+                );
+                total += prediction;
+            }
+            Err(error) => println!("Sequence {}: unable to fit ({:?})", i, error),
         }
     }
+    total
+}
 
-    // Now, perform prediction for each of them and percolate up to the top
-    let mut first_val = 0;
+fn fit(seq: &[i64]) -> Result<Fit, FitError> {
+    if seq.is_empty() {
+        return Err(FitError::Empty);
+    }
+    let mut newton: Vec<BigInt> = vec![];
+    let mut row: Vec<BigInt> = seq.iter().map(|v| BigInt::from(*v)).collect();
     loop {
-        let last_diffs = diff_seqs.pop().unwrap();
-        first_val = last_diffs.first().unwrap() - first_val;
-        if diff_seqs.is_empty() {
-            break;
+        if is_all_zero(&row) {
+            return Ok(Fit { newton });
+        }
+        newton.push(row[0].clone());
+        row = differences(&row);
+        if row.is_empty() {
+            return Err(FitError::NeverZero);
         }
     }
-
-    return first_val;
 }
 
-fn differences(seq: &Vec<i64>) -> Vec<i64> {
-    let mut differences: Vec<i64> = vec![];
-    let mut last_val = None;
-    for val in seq.iter() {
-        if let Some(last) = last_val {
-            differences.push(*val - last);
-            last_val = Some(*val);
-        } else {
-            last_val = Some(*val);
-        }
-    }
-    return differences;
+fn differences(seq: &[BigInt]) -> Vec<BigInt> {
+    seq.windows(2).map(|w| &w[1] - &w[0]).collect()
 }
 
-fn is_all_zero(seq: &Vec<i64>) -> bool {
-    seq.iter().all(|val| *val == 0)
+fn is_all_zero(seq: &[BigInt]) -> bool {
+    seq.iter().all(|val| val.is_zero())
 }
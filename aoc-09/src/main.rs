@@ -1,18 +1,203 @@
 use nom::{
-    character::complete::{i64, newline, space0},
-    multi::many1,
+    character::complete::{char, i64, newline, space0},
+    combinator::{all_consuming, verify},
+    multi::{many1, separated_list1},
     sequence::{delimited, terminated},
     IResult,
 };
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+use std::env;
 use std::fs;
 
 fn main() {
     println!("Let's solve AOC-09!");
     let input = fs::read_to_string("aoc-09-input.txt").expect("Unable to read file");
-    let solution = solve_problem(&input);
+
+    // `--at INDEX` evaluates the fitted polynomials at some other index (counting the first
+    // value of each sequence as index 0) instead of just after the end.
+    // `--offsets -3,-1,1,10` instead forecasts every sequence at each of the given offsets,
+    // where +k is k steps past the last value and -k is k steps before the first.
+    let mut args = env::args().skip(1);
+    let mut index: Option<BigInt> = None;
+    let mut offsets: Option<Vec<i64>> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--at" => {
+                let value = args.next().expect("Expected an index after --at");
+                index = Some(value.parse().expect("Malformed index"));
+            }
+            "--offsets" => {
+                let value = args
+                    .next()
+                    .expect("Expected a list of offsets after --offsets");
+                let (_, list) =
+                    offset_list(&value).expect("Malformed offsets (they must be nonzero)");
+                offsets = Some(list);
+            }
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
+    if let Some(offsets) = offsets {
+        let (_, seqs) = problem_input(&input).expect("Failed to parse problem input");
+        let forecasts = forecast_all(&seqs, &offsets);
+        print_forecasts(&offsets, &forecasts);
+        return;
+    }
+
+    let solution = solve_problem(&input, index);
     println!("Solution: {}", solution);
 }
 
+/* --------------- */
+/* Data Structures */
+/* --------------- */
+
+// The polynomial through a sequence, stored in Newton form: the leading entry of each row
+// of the difference table, so that p(x) = newton[0] + newton[1] * C(x, 1) + newton[2] * C(x, 2) + ...
+// where the values of the sequence are p(0), p(1), and so on.
+#[derive(Debug, Clone)]
+struct Fit {
+    newton: Vec<BigInt>,
+}
+
+#[derive(Debug, Clone)]
+enum FitError {
+    // The difference table ran out of values before reaching a row of zeros, so nothing
+    // pins down the polynomial's degree
+    NeverZero,
+    Empty,
+}
+
+impl Fit {
+    // C(x, k) for integer x (even negative), built up term by term; each division is exact
+    fn eval(&self, x: &BigInt) -> BigInt {
+        let mut total = BigInt::zero();
+        let mut binomial = BigInt::one();
+        for (k, coefficient) in self.newton.iter().enumerate() {
+            if k > 0 {
+                binomial = binomial * (x - (k - 1)) / k;
+            }
+            total += coefficient * &binomial;
+        }
+        total
+    }
+
+    fn degree(&self) -> usize {
+        self.newton.iter().rposition(|c| !c.is_zero()).unwrap_or(0)
+    }
+
+    // The coefficients in the usual basis 1, x, x^2, ..., found by expanding each C(x, k)
+    fn coefficients(&self) -> Vec<BigRational> {
+        let mut coefficients = vec![BigRational::zero(); self.degree() + 1];
+        let mut binomial: Vec<BigRational> = vec![BigRational::one()];
+        for (k, newton) in self.newton.iter().enumerate().take(self.degree() + 1) {
+            if k > 0 {
+                // Multiply by (x - (k - 1)) / k
+                let shift = BigRational::from_integer(BigInt::from(k - 1));
+                let scale = BigRational::from_integer(BigInt::from(k));
+                let mut next = vec![BigRational::zero(); binomial.len() + 1];
+                for (i, b) in binomial.iter().enumerate() {
+                    next[i + 1] += b / &scale;
+                    next[i] -= b * &shift / &scale;
+                }
+                binomial = next;
+            }
+            let newton = BigRational::from_integer(newton.clone());
+            for (i, b) in binomial.iter().enumerate() {
+                coefficients[i] += &newton * b;
+            }
+        }
+        coefficients
+    }
+}
+
+// Reusable scratch space for forecasting many sequences. The difference table is worked out
+// in place with checked i64 arithmetic; anything that overflows is redone with big integers.
+#[derive(Debug, Default)]
+struct Forecaster {
+    row: Vec<i64>,
+    newton: Vec<i64>,
+}
+
+impl Forecaster {
+    fn forecast(&mut self, seq: &[i64], offsets: &[i64]) -> Result<Vec<BigInt>, FitError> {
+        let indices = offsets
+            .iter()
+            .map(|offset| offset_index(seq.len(), *offset));
+        if let Some(fast) = self.fit_checked(seq)? {
+            if let Some(values) = indices
+                .clone()
+                .map(|index| index.and_then(|index| eval_checked(fast, index)))
+                .collect::<Option<Vec<i64>>>()
+            {
+                return Ok(values.into_iter().map(BigInt::from).collect());
+            }
+        }
+        let fit = fit(seq)?;
+        Ok(offsets
+            .iter()
+            .map(|offset| fit.eval(&offset_index_big(seq.len(), *offset)))
+            .collect())
+    }
+
+    // Fills `newton` from the difference table, or returns None if a difference overflowed
+    fn fit_checked(&mut self, seq: &[i64]) -> Result<Option<&[i64]>, FitError> {
+        if seq.is_empty() {
+            return Err(FitError::Empty);
+        }
+        self.row.clear();
+        self.row.extend_from_slice(seq);
+        self.newton.clear();
+        while !self.row.iter().all(|val| *val == 0) {
+            self.newton.push(self.row[0]);
+            for i in 0..self.row.len() - 1 {
+                match self.row[i + 1].checked_sub(self.row[i]) {
+                    Some(diff) => self.row[i] = diff,
+                    None => return Ok(None),
+                }
+            }
+            self.row.pop();
+            if self.row.is_empty() {
+                return Err(FitError::NeverZero);
+            }
+        }
+        Ok(Some(&self.newton))
+    }
+}
+
+// Same as Fit::eval, but giving up on overflow
+fn eval_checked(newton: &[i64], x: i64) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut binomial: i64 = 1;
+    for (k, coefficient) in newton.iter().enumerate() {
+        if k > 0 {
+            let k = k as i64;
+            binomial = binomial.checked_mul(x.checked_sub(k - 1)?)? / k;
+        }
+        total = total.checked_add(coefficient.checked_mul(binomial)?)?;
+    }
+    Some(total)
+}
+
+fn offset_index(len: usize, offset: i64) -> Option<i64> {
+    if offset > 0 {
+        (len as i64 - 1).checked_add(offset)
+    } else {
+        Some(offset)
+    }
+}
+
+fn offset_index_big(len: usize, offset: i64) -> BigInt {
+    if offset > 0 {
+        BigInt::from(len) - 1 + offset
+    } else {
+        BigInt::from(offset)
+    }
+}
+
 /* ------- */
 /* Parsers */
 /* ------- */
@@ -29,58 +214,98 @@ fn padded_i64(input: &str) -> IResult<&str, i64> {
     delimited(space0, i64, space0)(input)
 }
 
+fn offset_list(input: &str) -> IResult<&str, Vec<i64>> {
+    all_consuming(separated_list1(
+        char(','),
+        verify(i64, |offset| *offset != 0),
+    ))(input)
+}
+
 /* ----- */
 /* Logic */
 /* ----- */
 
-fn solve_problem(input: &str) -> i64 {
+// Predicts the value at the given index for each sequence (by default, the one just after
+// its end) and adds them up; sequences that can't be fitted are reported and skipped.
+fn solve_problem(input: &str, index: Option<BigInt>) -> BigInt {
     let (_, seqs) = problem_input(input).expect("Failed to parse problem input");
-    let predictions: Vec<i64> = seqs.iter().map(predict).collect();
-    let total = predictions.iter().fold(0, |x, y| x + y);
-    return total;
+    let mut total = BigInt::zero();
+    for (i, seq) in seqs.iter().enumerate() {
+        let at = index.clone().unwrap_or_else(|| BigInt::from(seq.len()));
+        match fit(seq) {
+            Ok(fit) => {
+                let prediction = fit.eval(&at);
+                let coefficients: Vec<String> =
+                    fit.coefficients().iter().map(|c| c.to_string()).collect();
+                println!(
+                    "Sequence {}: degree {}, coefficients [{}], p({}) = {}",
+                    i,
+                    fit.degree(),
+                    coefficients.join(", "),
+                    at,
+                    prediction
+                );
+                total += prediction;
+            }
+            Err(error) => println!("Sequence {}: unable to fit ({:?})", i, error),
+        }
+    }
+    total
 }
 
-fn predict(seq: &Vec<i64>) -> i64 {
-    // Start by generating the list of 'derivative' sequences
-    let start_seq = seq.clone();
-    let mut last_seq: Vec<i64> = start_seq.clone();
-    let mut diff_seqs: Vec<Vec<i64>> = vec![start_seq];
-    loop {
-        let next_diff = differences(&last_seq);
-        last_seq = next_diff.clone();
-        diff_seqs.push(next_diff);
-        if is_all_zero(&last_seq) || last_seq.is_empty() {
-            break;
+// Forecasts every sequence at each of the offsets, sharing one set of buffers throughout
+fn forecast_all(seqs: &[Vec<i64>], offsets: &[i64]) -> Vec<Result<Vec<BigInt>, FitError>> {
+    let mut forecaster = Forecaster::default();
+    seqs.iter()
+        .map(|seq| forecaster.forecast(seq, offsets))
+        .collect()
+}
+
+fn print_forecasts(offsets: &[i64], forecasts: &[Result<Vec<BigInt>, FitError>]) {
+    let mut totals = vec![BigInt::zero(); offsets.len()];
+    for (i, forecast) in forecasts.iter().enumerate() {
+        match forecast {
+            Ok(values) => {
+                let row: Vec<String> = offsets
+                    .iter()
+                    .zip(values)
+                    .map(|(offset, value)| format!("{:+}: {}", offset, value))
+                    .collect();
+                println!("Sequence {}: {}", i, row.join(", "));
+                for (total, value) in totals.iter_mut().zip(values) {
+                    *total += value;
+                }
+            }
+            Err(error) => println!("Sequence {}: unable to fit ({:?})", i, error),
         }
     }
+    for (offset, total) in offsets.iter().zip(totals) {
+        println!("Total at {:+}: {}", offset, total);
+    }
+}
 
-    // Now, perform prediction for each of them and percolate up to the top
-    let mut last_val = 0;
+fn fit(seq: &[i64]) -> Result<Fit, FitError> {
+    if seq.is_empty() {
+        return Err(FitError::Empty);
+    }
+    let mut newton: Vec<BigInt> = vec![];
+    let mut row: Vec<BigInt> = seq.iter().map(|v| BigInt::from(*v)).collect();
     loop {
-        let mut last_diffs = diff_seqs.pop().unwrap();
-        last_val = last_diffs.pop().unwrap() + last_val;
-        if diff_seqs.is_empty() {
-            break;
+        if is_all_zero(&row) {
+            return Ok(Fit { newton });
+        }
+        newton.push(row[0].clone());
+        row = differences(&row);
+        if row.is_empty() {
+            return Err(FitError::NeverZero);
         }
     }
-
-    return last_val;
 }
 
-fn differences(seq: &Vec<i64>) -> Vec<i64> {
-    let mut differences: Vec<i64> = vec![];
-    let mut last_val = None;
-    for val in seq.iter() {
-        if let Some(last) = last_val {
-            differences.push(*val - last);
-            last_val = Some(*val);
-        } else {
-            last_val = Some(*val);
-        }
-    }
-    return differences;
+fn differences(seq: &[BigInt]) -> Vec<BigInt> {
+    seq.windows(2).map(|w| &w[1] - &w[0]).collect()
 }
 
-fn is_all_zero(seq: &Vec<i64>) -> bool {
-    seq.iter().all(|val| *val == 0)
+fn is_all_zero(seq: &[BigInt]) -> bool {
+    seq.iter().all(|val| val.is_zero())
 }
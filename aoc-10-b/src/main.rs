@@ -6,13 +6,37 @@ use nom::multi::many0;
 use nom::sequence::{preceded, terminated, tuple};
 use nom::{IResult, Offset, Parser};
 use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
+use std::env;
 use std::{collections::HashMap, fs};
 
 fn main() {
     println!("Let's solve AOC-10!");
     let input = fs::read_to_string("aoc-10-input.txt").expect("Unable to read file");
-    let solution = solve_problem(&input);
+
+    // `--shoelace` counts the enclosed tiles with the shoelace formula and Pick's theorem
+    // instead, checking the answer against the flood fill.
+    // `--loop N` and `--all-loops` pick loops other than the one through the start, and
+    // `--junk` lists every pipe that isn't part of a loop.
+    let mut method = AreaMethod::FloodFill;
+    let mut selection = LoopSelection::Start;
+    let mut list_junk = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--shoelace" => method = AreaMethod::Shoelace,
+            "--flood-fill" => method = AreaMethod::FloodFill,
+            "--loop" => {
+                let index = args.next().expect("Expected a loop number after --loop");
+                selection = LoopSelection::Index(index.parse().expect("Malformed loop number"));
+            }
+            "--all-loops" => selection = LoopSelection::All,
+            "--junk" => list_junk = true,
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
+    let solution = solve_problem(&input, method, selection, list_junk);
     println!("Solution: {}", solution);
 }
 
@@ -106,6 +130,43 @@ pub struct TileFillData {
     some_blue: Option<(i64, i64)>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AreaMethod {
+    FloodFill,
+    Shoelace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopSelection {
+    Start,
+    Index(usize),
+    All,
+}
+
+// A closed loop of pipe, with its tiles in the order they are traversed
+#[derive(Debug, Clone)]
+struct PipeLoop {
+    tiles: Vec<((i64, i64), PipeSegment)>,
+}
+
+impl PipeLoop {
+    fn contains(&self, loc: (i64, i64)) -> bool {
+        self.tiles.iter().any(|(l, _)| *l == loc)
+    }
+
+    // Running both ways around the loop from its first tile, the runners meet halfway
+    fn farthest_distance(&self) -> usize {
+        self.tiles.len() / 2
+    }
+}
+
+// Pipe tiles that aren't part of any loop, and the ends of pipes that don't lead anywhere
+#[derive(Debug, Clone, Default)]
+struct JunkReport {
+    dangling: Vec<((i64, i64), Direction)>,
+    disconnected: Vec<(i64, i64)>,
+}
+
 // State necessary for a process running along the pipe in one direction
 #[derive(Debug, Clone, Copy)]
 struct PipeRunnerState {
@@ -257,42 +318,300 @@ where
 /* Logic */
 /* ----- */
 
-fn solve_problem(input: &str) -> i64 {
+fn solve_problem(
+    input: &str,
+    method: AreaMethod,
+    selection: LoopSelection,
+    list_junk: bool,
+) -> i64 {
     let (_, ((width, height), pipe_map)) =
         problem_input(input).expect("Failed to parse problem input");
-    let start = pipe_map
-        .start
-        .expect("Failed to find the starting position");
-    let mut starting_data: Vec<PipeRunnerState> = vec![];
-    let mut starting_dirs: Vec<Direction> = vec![];
-
-    // Look at each direction; the first time we find a valid direction, go that way.
-    for starting_dir in EACH_DIRECTION.iter() {
-        let next_coord = coord_in_dir(start, *starting_dir);
-        match pipe_map.map.get(&next_coord) {
-            Some(segment) => {
-                if segment.incoming_dirs().contains(starting_dir) {
-                    let new_state = PipeRunnerState {
-                        current_location: next_coord,
-                        current_segment: *segment,
-                        last_direction: *starting_dir,
-                    };
-                    starting_data.push(new_state);
-                    starting_dirs.push(*starting_dir);
+    let start_segments = start_segments(&pipe_map);
+    if let Some(start) = pipe_map.start.filter(|_| start_segments.is_empty()) {
+        println!(
+            "Warning: the start at {:?} is not on any closed loop",
+            start
+        );
+    }
+
+    let loops = find_loops(&pipe_map, &start_segments);
+    let junk = find_junk(&pipe_map, &loops);
+    println!(
+        "Found {} closed loop(s); {} pipe end(s) dangle, and {} pipe tile(s) are not part of any loop",
+        loops.len(),
+        junk.dangling.len(),
+        junk.disconnected.len()
+    );
+    if list_junk {
+        for (loc, dir) in junk.dangling.iter() {
+            println!("Dangling: the pipe at {:?} leads {:?} to nothing", loc, dir);
+        }
+        for loc in junk.disconnected.iter() {
+            println!("Disconnected: {:?}", loc);
+        }
+    }
+
+    let selected: Vec<usize> = match selection {
+        LoopSelection::Start => {
+            let through_start: Vec<usize> = match pipe_map.start {
+                Some(start) => (0..loops.len())
+                    .filter(|i| loops[*i].contains(start))
+                    .collect(),
+                None => vec![],
+            };
+            if through_start.len() > 1 {
+                println!(
+                    "The start is on loops {:?}; using loop {} (pick another with --loop N)",
+                    through_start, through_start[0]
+                );
+            }
+            match through_start.first() {
+                Some(i) => vec![*i],
+                None => {
+                    // No usable start, so fall back to the longest loop there is
+                    let longest = (0..loops.len()).max_by_key(|i| loops[*i].tiles.len());
+                    if let Some(i) = longest {
+                        println!(
+                            "Using loop {} (the longest) in place of the start's loop",
+                            i
+                        );
+                    }
+                    longest.into_iter().collect()
                 }
             }
-            None => {
-                continue;
+        }
+        LoopSelection::Index(i) => {
+            if i >= loops.len() {
+                panic!("There is no loop {} (found {} loops)", i, loops.len());
+            }
+            vec![i]
+        }
+        LoopSelection::All => (0..loops.len()).collect(),
+    };
+
+    let mut total = 0;
+    for i in selected {
+        let pipe_loop = &loops[i];
+        let area = loop_area(width, height, pipe_loop, method);
+        println!(
+            "Loop {}: {} tiles, farthest point {} steps away, {} tiles enclosed{}",
+            i,
+            pipe_loop.tiles.len(),
+            pipe_loop.farthest_distance(),
+            area,
+            if pipe_map
+                .start
+                .is_some_and(|start| pipe_loop.contains(start))
+            {
+                " (through the start)"
+            } else {
+                ""
+            }
+        );
+        total += area;
+    }
+    total
+}
+
+fn loop_area(width: usize, height: usize, pipe_loop: &PipeLoop, method: AreaMethod) -> i64 {
+    let flood_fill = flood_fill_area(width, height, pipe_loop);
+    match method {
+        AreaMethod::FloodFill => flood_fill,
+        AreaMethod::Shoelace => {
+            let shoelace = shoelace_area(&pipe_loop.tiles);
+            if shoelace != flood_fill {
+                println!(
+                    "Warning: the shoelace formula found {} enclosed tiles, but the flood fill found {}",
+                    shoelace, flood_fill
+                );
+            }
+            shoelace
+        }
+    }
+}
+
+// By the shoelace formula, twice the area of the polygon through the centres of the corner
+// tiles is the absolute value of sum (x_i * y_{i+1} - x_{i+1} * y_i). Then Pick's theorem,
+// A = I + B/2 - 1, gives the number of interior lattice points I (i.e. enclosed tiles),
+// where B is the length of the loop, since every pipe tile is a boundary point.
+fn shoelace_area(history: &[((i64, i64), PipeSegment)]) -> i64 {
+    let vertices: Vec<(i64, i64)> = history
+        .iter()
+        .filter(|(_, segment)| *segment != PipeSegment::NS && *segment != PipeSegment::EW)
+        .map(|(loc, _)| *loc)
+        .collect();
+    let twice_area: i64 = vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(|((x_0, y_0), (x_1, y_1))| x_0 * y_1 - x_1 * y_0)
+        .sum::<i64>()
+        .abs();
+    let boundary = history.len() as i64;
+    (twice_area - boundary + 2) / 2
+}
+
+// The start may connect to more than two of its neighbours, so we try every segment type that
+// it could be; each one that actually closes a loop gives a different loop through the start.
+fn start_segments(pipe_map: &PipeMap) -> Vec<PipeSegment> {
+    let Some(start) = pipe_map.start else {
+        return vec![];
+    };
+    let connecting: Vec<Direction> = EACH_DIRECTION
+        .iter()
+        .filter(|dir| connects(&pipe_map.map, start, **dir))
+        .copied()
+        .collect();
+    let candidates: Vec<PipeSegment> = EACH_SEGMENT
+        .iter()
+        .filter(|segment| {
+            segment
+                .outgoing_dirs()
+                .iter()
+                .all(|d| connecting.contains(d))
+        })
+        .copied()
+        .collect();
+    if candidates.len() > 1 {
+        println!(
+            "The start connects to {} neighbours; trying each of {:?}",
+            connecting.len(),
+            candidates
+        );
+    }
+
+    let mut map = pipe_map.map.clone();
+    let closing: Vec<PipeSegment> = candidates
+        .into_iter()
+        .filter(|segment| {
+            map.insert(start, *segment);
+            walk_loop(&map, start).is_ok()
+        })
+        .collect();
+    if closing.len() > 1 {
+        println!(
+            "The start could be any of {:?}, so it is on {} loops",
+            closing,
+            closing.len()
+        );
+    }
+    closing
+}
+
+// Whether the pipe at `loc` leads in `dir` to a pipe that leads back again
+fn connects(map: &HashMap<(i64, i64), PipeSegment>, loc: (i64, i64), dir: Direction) -> bool {
+    match map.get(&coord_in_dir(loc, dir)) {
+        Some(PipeSegment::Start) | None => false,
+        Some(segment) => segment.incoming_dirs().contains(&dir),
+    }
+}
+
+// Follows the pipe from `from` until it comes back around, giving the loop in order; if the pipe
+// breaks off instead, gives back the tiles that were visited, none of which can be on a loop.
+fn walk_loop(
+    map: &HashMap<(i64, i64), PipeSegment>,
+    from: (i64, i64),
+) -> Result<PipeLoop, Vec<(i64, i64)>> {
+    let mut segment = match map.get(&from) {
+        Some(PipeSegment::Start) | None => return Err(vec![from]),
+        Some(segment) => *segment,
+    };
+    let mut tiles = vec![(from, segment)];
+    let mut loc = from;
+    let mut dir = segment.outgoing_dirs()[0];
+    loop {
+        if !connects(map, loc, dir) {
+            return Err(tiles.into_iter().map(|(loc, _)| loc).collect());
+        }
+        loc = coord_in_dir(loc, dir);
+        if loc == from {
+            return Ok(PipeLoop { tiles });
+        }
+        segment = map[&loc];
+        tiles.push((loc, segment));
+        dir = *segment
+            .outgoing_dirs()
+            .iter()
+            .find(|d| **d != dir.opposite())
+            .unwrap();
+    }
+}
+
+// Every closed loop in the map, found by walking from each tile not yet accounted for (in
+// reading order, which is also how the loops end up numbered). The start is left unresolved
+// in the map, so no other walk can pass through it; instead, it gets one loop for each of the
+// segments it could be.
+fn find_loops(pipe_map: &PipeMap, start_segments: &[PipeSegment]) -> Vec<PipeLoop> {
+    let mut locs: Vec<(i64, i64)> = pipe_map.map.keys().copied().collect();
+    locs.sort_by_key(|(x, y)| (*y, *x));
+    let mut visited: HashSet<(i64, i64)> = HashSet::new();
+    let mut loops = vec![];
+    for loc in locs {
+        if visited.contains(&loc) {
+            continue;
+        }
+        if pipe_map.start == Some(loc) {
+            let mut map = pipe_map.map.clone();
+            for segment in start_segments {
+                map.insert(loc, *segment);
+                let pipe_loop =
+                    walk_loop(&map, loc).expect("The start segment should close a loop");
+                visited.extend(pipe_loop.tiles.iter().map(|(loc, _)| *loc));
+                loops.push(pipe_loop);
+            }
+            visited.insert(loc);
+            continue;
+        }
+        match walk_loop(&pipe_map.map, loc) {
+            Ok(pipe_loop) => {
+                visited.extend(pipe_loop.tiles.iter().map(|(loc, _)| *loc));
+                loops.push(pipe_loop);
             }
+            Err(walked) => visited.extend(walked),
         }
     }
-    // We ensure that the actual type of the starting block is recorded properly; this can matter
-    // for computing intersection numbers later on, so this is to avoid corner cases
-    let starting_data = starting_data
-        .first()
-        .expect("Failed to find a starting direction");
-    let start_type =
-        find_type_of_start(&starting_dirs).expect("Failed to find type of starting location");
+    loops
+}
+
+fn find_junk(pipe_map: &PipeMap, loops: &[PipeLoop]) -> JunkReport {
+    let on_loop: HashSet<(i64, i64)> = loops
+        .iter()
+        .flat_map(|l| l.tiles.iter().map(|(loc, _)| *loc))
+        .collect();
+    let mut report = JunkReport::default();
+    let mut locs: Vec<(i64, i64)> = pipe_map.map.keys().copied().collect();
+    locs.sort_by_key(|(x, y)| (*y, *x));
+    for loc in locs {
+        if on_loop.contains(&loc) {
+            continue;
+        }
+        report.disconnected.push(loc);
+        let segment = pipe_map.map[&loc];
+        if segment == PipeSegment::Start {
+            continue;
+        }
+        // The start could be connected to anything, so pipes leading into it don't dangle
+        for dir in segment.outgoing_dirs() {
+            if !connects(&pipe_map.map, loc, dir) && pipe_map.start != Some(coord_in_dir(loc, dir))
+            {
+                report.dangling.push((loc, dir));
+            }
+        }
+    }
+    report
+}
+
+fn flood_fill_area(width: usize, height: usize, pipe_loop: &PipeLoop) -> i64 {
+    // The runner sets off from the second tile of the loop and stops when it gets back to the first
+    let (start, start_type) = pipe_loop.tiles[0];
+    let (next, next_type) = pipe_loop.tiles[1];
+    let starting_data = PipeRunnerState {
+        current_location: next,
+        current_segment: next_type,
+        last_direction: *EACH_DIRECTION
+            .iter()
+            .find(|dir| coord_in_dir(start, **dir) == next)
+            .unwrap(),
+    };
 
     // Now, we set our guy running along the loop and updating all these wonderful mutable things:
     // > The loop history and the loop index record very similar information, searchable in different ways
@@ -307,8 +626,8 @@ fn solve_problem(input: &str) -> i64 {
     let mut red_to_check: VecDeque<(i64, i64)> = VecDeque::new();
     let mut blue_to_check: VecDeque<(i64, i64)> = VecDeque::new();
     run_pipe(
-        *starting_data,
-        &pipe_map,
+        starting_data,
+        pipe_loop,
         &mut loop_history,
         &mut index,
         &mut red_to_check,
@@ -398,13 +717,15 @@ fn solve_problem(input: &str) -> i64 {
 
 fn run_pipe(
     start_datum: PipeRunnerState,
-    pipe_map: &PipeMap,
+    pipe_loop: &PipeLoop,
     history: &mut Vec<((i64, i64), PipeSegment)>,
     index: &mut HashMap<(i64, i64), TileType>,
     red_queue: &mut VecDeque<(i64, i64)>,
     blue_queue: &mut VecDeque<(i64, i64)>,
 ) {
     let mut state = start_datum;
+    let map: HashMap<(i64, i64), PipeSegment> = pipe_loop.tiles.iter().copied().collect();
+    let first = pipe_loop.tiles[0].0;
     'main: loop {
         // Update shared information:
         history.push((state.current_location, state.current_segment));
@@ -430,10 +751,10 @@ fn run_pipe(
         for dir in state.current_segment.outgoing_dirs().iter() {
             if *dir != state.last_direction.opposite() {
                 let next_coord = coord_in_dir(state.current_location, *dir);
-                match pipe_map.map.get(&next_coord) {
+                match map.get(&next_coord) {
                     Some(segment) => {
                         if segment.incoming_dirs().contains(dir) {
-                            if *segment == PipeSegment::Start {
+                            if next_coord == first {
                                 return;
                             } else {
                                 state.current_segment = *segment;
@@ -476,6 +797,15 @@ const EACH_DIRECTION: [Direction; 4] = [
     Direction::West,
 ];
 
+const EACH_SEGMENT: [PipeSegment; 6] = [
+    PipeSegment::NS,
+    PipeSegment::EW,
+    PipeSegment::NE,
+    PipeSegment::NW,
+    PipeSegment::SE,
+    PipeSegment::SW,
+];
+
 // Ray-casting algorithm for testing whether a point is inside the loop;
 // Chose a diagonal direction so that the line is never tangent to the loop --
 // here we go southeast (the line x=y in my coordinates) and notice that the
@@ -561,25 +891,3 @@ fn paint(
         PipeSegment::Start => None, // Should never be called
     }
 }
-
-fn find_type_of_start(start_dirs: &Vec<Direction>) -> Option<PipeSegment> {
-    if start_dirs.contains(&Direction::North) && start_dirs.contains(&Direction::South) {
-        return Some(PipeSegment::NS);
-    }
-    if start_dirs.contains(&Direction::East) && start_dirs.contains(&Direction::West) {
-        return Some(PipeSegment::EW);
-    }
-    if start_dirs.contains(&Direction::North) && start_dirs.contains(&Direction::East) {
-        return Some(PipeSegment::NE);
-    }
-    if start_dirs.contains(&Direction::North) && start_dirs.contains(&Direction::West) {
-        return Some(PipeSegment::NW);
-    }
-    if start_dirs.contains(&Direction::South) && start_dirs.contains(&Direction::East) {
-        return Some(PipeSegment::SE);
-    }
-    if start_dirs.contains(&Direction::South) && start_dirs.contains(&Direction::West) {
-        return Some(PipeSegment::SW);
-    }
-    return None;
-}
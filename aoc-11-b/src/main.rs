@@ -1,11 +1,12 @@
 use nom::bytes::complete::take_until;
-use nom::character::complete::{char, newline};
-use nom::combinator::map;
+use nom::character::complete::{char, newline, u64};
+use nom::combinator::{all_consuming, map, verify};
 use nom::error::ParseError;
-use nom::multi::many0;
+use nom::multi::{many0, separated_list1};
 use nom::sequence::{pair, preceded, tuple};
 use nom::{IResult, Offset, Parser};
 use std::collections::BTreeMap;
+use std::env;
 use std::{cell::RefCell, collections::HashMap, fs};
 
 use std::time::Instant;
@@ -14,7 +15,64 @@ fn main() {
     println!("Let's solve AOC-11!");
     let now = Instant::now();
     let input = fs::read_to_string("aoc-11-input.txt").expect("Unable to read file");
-    let solution = solve_problem(&input);
+
+    // `--factor N` sets the expansion factor of blank rows and columns alike (so Part 1 is
+    // `--factor 2`), while `--row-factor N` and `--column-factor N` set them separately.
+    // `--factors 2,10,100` additionally answers for each of several factors.
+    // The rest are queries about individual galaxies (numbered from 1 in reading order, as in
+    // the puzzle) in the expanded universe: `--distance A B`, `--nearest A K`, `--farthest` and
+    // `--histogram WIDTH` (which buckets all of the pairwise distances).
+    let mut row_factor = 1000000;
+    let mut column_factor = 1000000;
+    let mut extra_factors: Vec<usize> = vec![];
+    let mut queries: Vec<Query> = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut next_arg = |what: &str| args.next().unwrap_or_else(|| panic!("Expected {what}"));
+        let mut next_factors = || {
+            let (_, factors) = factor_list(&next_arg("expansion factor(s)"))
+                .expect("Malformed factor (must be at least 1)");
+            factors
+        };
+        match arg.as_str() {
+            "--factor" => {
+                row_factor = next_factors()[0];
+                column_factor = row_factor;
+            }
+            "--row-factor" => row_factor = next_factors()[0],
+            "--column-factor" => column_factor = next_factors()[0],
+            "--factors" => extra_factors = next_factors(),
+            "--distance" => queries.push(Query::Distance(
+                parse_number(next_arg("a galaxy number")),
+                parse_number(next_arg("a galaxy number")),
+            )),
+            "--nearest" => queries.push(Query::Nearest(
+                parse_number(next_arg("a galaxy number")),
+                parse_number(next_arg("a number of neighbours")),
+            )),
+            "--farthest" => queries.push(Query::Farthest),
+            "--histogram" => {
+                queries.push(Query::Histogram(parse_number(next_arg("a bucket width"))))
+            }
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
+    let formula = solve_problem(&input);
+    println!(
+        "Total distance = {} + (r - 1) * {} + {} + (c - 1) * {} for row factor r and column factor c",
+        formula.rows.base, formula.rows.crossings, formula.columns.base, formula.columns.crossings
+    );
+    for factor in extra_factors {
+        println!("Factor {}: {}", factor, formula.at(factor, factor));
+    }
+    let solution = formula.at(row_factor, column_factor);
+    if !queries.is_empty() {
+        let universe = expand_universe(&input, row_factor, column_factor);
+        for query in queries {
+            answer_query(&universe, query);
+        }
+    }
     println!("Elapsed: {:?}", now.elapsed());
     println!("Solution: {}", solution);
 }
@@ -27,6 +85,125 @@ type GalaxyMap = HashMap<(usize, usize), usize>;
 
 type Dims = (usize, usize);
 
+// The sum of pairwise distances along one axis is affine in the expansion factor: every blank
+// line between a pair of galaxies adds (factor - 1) to their distance, so the total is
+// `base + (factor - 1) * crossings`, where `crossings` counts blank lines over all pairs.
+#[derive(Debug, Clone, Copy)]
+struct AxisTotal {
+    base: usize,
+    crossings: usize,
+}
+
+impl AxisTotal {
+    fn at(&self, factor: usize) -> usize {
+        self.base + (factor - 1) * self.crossings
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DistanceFormula {
+    rows: AxisTotal,
+    columns: AxisTotal,
+}
+
+impl DistanceFormula {
+    fn at(&self, row_factor: usize, column_factor: usize) -> usize {
+        self.rows.at(row_factor) + self.columns.at(column_factor)
+    }
+}
+
+// The galaxies in reading order, along with the threshold tables for looking up how far each
+// row and column has been pushed out by the blank ones before it
+#[derive(Debug, Clone)]
+struct Universe {
+    galaxies: Vec<(usize, usize)>,
+    row_thresholds: Vec<(usize, usize)>,
+    column_thresholds: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Query {
+    Distance(usize, usize),
+    Nearest(usize, usize),
+    Farthest,
+    Histogram(usize),
+}
+
+impl Universe {
+    fn expanded(&self, galaxy: usize) -> (usize, usize) {
+        let (x, y) = self.galaxies[galaxy];
+        (
+            x + threshold_offset(&self.column_thresholds, x),
+            y + threshold_offset(&self.row_thresholds, y),
+        )
+    }
+
+    fn distance(&self, a: usize, b: usize) -> usize {
+        let (x_a, y_a) = self.expanded(a);
+        let (x_b, y_b) = self.expanded(b);
+        x_a.abs_diff(x_b) + y_a.abs_diff(y_b)
+    }
+
+    // The k galaxies closest to `galaxy`, nearest first, with their distances
+    fn nearest(&self, galaxy: usize, k: usize) -> Vec<(usize, usize)> {
+        let mut others: Vec<(usize, usize)> = (0..self.galaxies.len())
+            .filter(|other| *other != galaxy)
+            .map(|other| (other, self.distance(galaxy, other)))
+            .collect();
+        if k < others.len() {
+            others.select_nth_unstable_by_key(k, |(other, d)| (*d, *other));
+            others.truncate(k);
+        }
+        others.sort_by_key(|(other, d)| (*d, *other));
+        others
+    }
+
+    // Taxicab distance is the larger of |dx + dy| and |dx - dy|, so the farthest pair is found
+    // among the extremes of x + y and x - y
+    fn farthest_pair(&self) -> Option<(usize, usize, usize)> {
+        if self.galaxies.len() < 2 {
+            return None;
+        }
+        let sum = |g: &usize| {
+            let (x, y) = self.expanded(*g);
+            x + y
+        };
+        let difference = |g: &usize| {
+            let (x, y) = self.expanded(*g);
+            x as i64 - y as i64
+        };
+        let all = 0..self.galaxies.len();
+        let candidates = [
+            (
+                all.clone().min_by_key(sum).unwrap(),
+                all.clone().max_by_key(sum).unwrap(),
+            ),
+            (
+                all.clone().min_by_key(difference).unwrap(),
+                all.clone().max_by_key(difference).unwrap(),
+            ),
+        ];
+        candidates
+            .into_iter()
+            .map(|(a, b)| (a.min(b), a.max(b), self.distance(a, b)))
+            .max_by_key(|(_, _, d)| *d)
+    }
+
+    // Counts of pairwise distances, keyed by the start of each bucket
+    fn histogram(&self, width: usize) -> BTreeMap<usize, usize> {
+        let mut buckets: BTreeMap<usize, usize> = BTreeMap::new();
+        let expanded: Vec<(usize, usize)> =
+            (0..self.galaxies.len()).map(|g| self.expanded(g)).collect();
+        for (i, (x_a, y_a)) in expanded.iter().enumerate() {
+            for (x_b, y_b) in expanded[i + 1..].iter() {
+                let d = x_a.abs_diff(*x_b) + y_a.abs_diff(*y_b);
+                *buckets.entry(d / width * width).or_insert(0) += 1;
+            }
+        }
+        buckets
+    }
+}
+
 /* ------- */
 /* Parsers */
 /* ------- */
@@ -118,6 +295,19 @@ fn galaxy(input: &str) -> IResult<&str, char> {
     preceded(many0(char('.')), char('#'))(input)
 }
 
+fn parse_number(input: String) -> usize {
+    input
+        .parse()
+        .unwrap_or_else(|_| panic!("Malformed number: {input}"))
+}
+
+fn factor_list(input: &str) -> IResult<&str, Vec<usize>> {
+    all_consuming(separated_list1(
+        char(','),
+        map(verify(u64, |factor| *factor >= 1), |factor| factor as usize),
+    ))(input)
+}
+
 fn with_offset<F, I, O, E>(mut parser: F) -> impl FnMut(I) -> IResult<I, (usize, O), E>
 where
     F: Parser<I, O, E>,
@@ -142,12 +332,10 @@ where
 /* Logic */
 /* ----- */
 
-fn solve_problem(input: &str) -> usize {
+fn solve_problem(input: &str) -> DistanceFormula {
     let (_, ((width, height), galaxy_map)) =
         problem_input(input).expect("Failed to parse problem input");
 
-    // All of this is just doing expansion:
-    // -----------------------------------
     let mut row_mass: BTreeMap<usize, usize> = BTreeMap::new();
     let mut column_mass: BTreeMap<usize, usize> = BTreeMap::new();
     let mut total_mass: usize = 0;
@@ -157,39 +345,110 @@ fn solve_problem(input: &str) -> usize {
         total_mass += 1;
     }
 
-    // Iterate over rows and columns;
-    // for each blank one, we store (val, location), so that every point with x-coordinate (say)
-    // `x > location` (and preceding the preceding marker) needs to have its value increased by `val`
-    let mut row_thresholds: Vec<(usize, usize)> = vec![];
-    let mut blank_rows = 0;
-    for i in 0..height {
-        if !row_mass.contains_key(&i) {
-            blank_rows += 999999;
-            row_thresholds.push((blank_rows, i));
-        }
+    DistanceFormula {
+        rows: axis_total(&row_mass, height, total_mass),
+        columns: axis_total(&column_mass, width, total_mass),
     }
-    let mut column_thresholds: Vec<(usize, usize)> = vec![];
-    let mut blank_columns = 0;
-    for i in 0..width {
-        if !column_mass.contains_key(&i) {
-            blank_columns += 999999;
-            column_thresholds.push((blank_columns, i));
-        }
+}
+
+// Finds the affine function by totalling the distances without any expansion and with
+// each blank line counting double (i.e. at factors 1 and 2)
+fn axis_total(mass_function: &BTreeMap<usize, usize>, size: usize, total_mass: usize) -> AxisTotal {
+    let total_with_increment = |increment| {
+        let mut thresholds = blank_thresholds(mass_function, size, increment);
+        let (mut expanded_mass, weighted_total) =
+            expand_mass_function(&mut mass_function.clone(), &mut thresholds);
+        linear_distance_total(&mut expanded_mass, weighted_total, total_mass)
+    };
+    let base = total_with_increment(0);
+    AxisTotal {
+        base,
+        crossings: total_with_increment(1) - base,
     }
+}
 
-    // This pair of loops is where we finally do the actual work of replacing the mass
-    // functions with the expanded versions.
-    let (mut expanded_row_mass, weighted_row_total) =
-        expand_mass_function(&mut row_mass, &mut row_thresholds);
-    let (mut expanded_column_mass, weighted_column_total) =
-        expand_mass_function(&mut column_mass, &mut column_thresholds);
+fn expand_universe(input: &str, row_factor: usize, column_factor: usize) -> Universe {
+    let (_, ((width, height), galaxy_map)) =
+        problem_input(input).expect("Failed to parse problem input");
+    let mut galaxies: Vec<(usize, usize)> = galaxy_map.keys().copied().collect();
+    galaxies.sort_by_key(|(x, y)| (*y, *x));
 
-    // Now we have the finalized versions of our row and column mass functions;
-    // using them, we compute the total distance
-    let row_total = linear_distance_total(&mut expanded_row_mass, weighted_row_total, total_mass);
-    let column_total =
-        linear_distance_total(&mut expanded_column_mass, weighted_column_total, total_mass);
-    return row_total + column_total;
+    let mut row_mass: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut column_mass: BTreeMap<usize, usize> = BTreeMap::new();
+    for (x, y) in galaxies.iter() {
+        *row_mass.entry(*y).or_insert(0) += 1;
+        *column_mass.entry(*x).or_insert(0) += 1;
+    }
+    Universe {
+        galaxies,
+        row_thresholds: blank_thresholds(&row_mass, height, row_factor - 1),
+        column_thresholds: blank_thresholds(&column_mass, width, column_factor - 1),
+    }
+}
+
+// The offset recorded by the last threshold below `pt`, found by binary search
+fn threshold_offset(thresholds: &[(usize, usize)], pt: usize) -> usize {
+    let index = thresholds.partition_point(|(_, location)| *location < pt);
+    if index == 0 {
+        0
+    } else {
+        thresholds[index - 1].0
+    }
+}
+
+fn answer_query(universe: &Universe, query: Query) {
+    let count = universe.galaxies.len();
+    let check = |galaxy: usize| {
+        if galaxy == 0 || galaxy > count {
+            panic!("There is no galaxy {} (there are {})", galaxy, count);
+        }
+        galaxy - 1
+    };
+    match query {
+        Query::Distance(a, b) => {
+            let distance = universe.distance(check(a), check(b));
+            println!("Distance from galaxy {} to galaxy {}: {}", a, b, distance);
+        }
+        Query::Nearest(a, k) => {
+            let nearest: Vec<String> = universe
+                .nearest(check(a), k)
+                .iter()
+                .map(|(other, d)| format!("{} ({})", other + 1, d))
+                .collect();
+            println!("Nearest to galaxy {}: {}", a, nearest.join(", "));
+        }
+        Query::Farthest => match universe.farthest_pair() {
+            Some((a, b, d)) => println!("Farthest pair: galaxies {} and {} ({})", a + 1, b + 1, d),
+            None => println!("Farthest pair: there are fewer than two galaxies"),
+        },
+        Query::Histogram(width) => {
+            if width == 0 {
+                panic!("The bucket width must be positive");
+            }
+            for (start, pairs) in universe.histogram(width) {
+                println!("{}..{}: {}", start, start + width, pairs);
+            }
+        }
+    }
+}
+
+// Iterate over rows (or columns);
+// for each blank one, we store (val, location), so that every point with x-coordinate (say)
+// `x > location` (and preceding the preceding marker) needs to have its value increased by `val`
+fn blank_thresholds(
+    mass_function: &BTreeMap<usize, usize>,
+    size: usize,
+    increment: usize,
+) -> Vec<(usize, usize)> {
+    let mut thresholds: Vec<(usize, usize)> = vec![];
+    let mut blanks = 0;
+    for i in 0..size {
+        if !mass_function.contains_key(&i) {
+            blanks += increment;
+            thresholds.push((blanks, i));
+        }
+    }
+    thresholds
 }
 
 fn expand_mass_function(
@@ -1,4 +1,5 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
 use std::fs;
 
 use nalgebra::{DMatrix, DVector, OMatrix};
@@ -9,14 +10,108 @@ use nom::combinator::{map, opt};
 use nom::multi::{many0, many1};
 use nom::sequence::separated_pair;
 use nom::{sequence::terminated, IResult};
+use num_bigint::BigInt;
+use num_traits::{CheckedAdd, CheckedMul, CheckedSub, One, Zero};
+use std::fmt::Display;
 
 fn main() {
     println!("Let's solve AOC-12!");
     let now = std::time::Instant::now();
     let input = fs::read_to_string("aoc-12-input.txt").expect("Unable to read file");
-    let solution = solve_problem(&input);
+
+    // `--row N` picks out a single row (numbered from 1) to look at in detail: `--list` prints
+    // all of its arrangements in lexicographic order (up to `--limit M` of them), `--kth K`
+    // prints the K-th arrangement in that order (counting from 0), and `--sample COUNT` draws
+    // arrangements uniformly at random (reproducibly, from `--seed S`).
+    // `--unfold N` makes N copies of each row rather than five (and `--folded` is the same as
+    // `--unfold 1`, i.e. Part 1), while `--periodic` counts using the repeating structure of
+    // the unfolded rows rather than laying them out in full, which is better for large N.
+    // `--dp` counts with the usual dynamic programming over (position, block) instead, and
+    // `--cross-check` runs both that and the matrix method on every row, comparing the two.
+    let mut row: Option<usize> = None;
+    let mut operations: Vec<RowOperation> = vec![];
+    let mut limit: Option<usize> = None;
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut copies: usize = 5;
+    let mut method = CountMethod::Matrices;
+    let mut cross_check = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut next_arg = |what: &str| -> String {
+            args.next()
+                .unwrap_or_else(|| panic!("Expected {what} after {arg}"))
+        };
+        match arg.as_str() {
+            "--row" => {
+                row = Some(
+                    next_arg("a row number")
+                        .parse()
+                        .expect("Malformed row number"),
+                )
+            }
+            "--list" => operations.push(RowOperation::List),
+            "--limit" => limit = Some(next_arg("a limit").parse().expect("Malformed limit")),
+            "--kth" => operations.push(RowOperation::Kth(
+                next_arg("an index").parse().expect("Malformed index"),
+            )),
+            "--sample" => operations.push(RowOperation::Sample(
+                next_arg("a sample count")
+                    .parse()
+                    .expect("Malformed sample count"),
+            )),
+            "--seed" => seed = next_arg("a seed").parse().expect("Malformed seed"),
+            "--folded" => copies = 1,
+            "--unfold" => {
+                copies = next_arg("an unfold factor")
+                    .parse()
+                    .expect("Malformed unfold factor");
+                if copies == 0 {
+                    panic!("The unfold factor must be at least 1");
+                }
+            }
+            "--periodic" => method = CountMethod::Periodic,
+            "--dp" => method = CountMethod::Dp,
+            "--cross-check" => cross_check = true,
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
+    if let Some(row) = row {
+        let (_, problems) = problem_input(&input).expect("Failed to parse problem input");
+        let problem = problems
+            .get(row.wrapping_sub(1))
+            .unwrap_or_else(|| panic!("There is no row {} (there are {})", row, problems.len()));
+        println!(
+            "Row {} has {} arrangements",
+            row,
+            count_row(problem, copies, method)
+        );
+        if operations.is_empty() {
+            return;
+        }
+
+        // Walking through the arrangements needs the row laid out in full, whatever the method
+        let mut problem = problem.clone();
+        expand_problem(&mut problem, copies);
+        println!("{}", render_states(&problem.states));
+        match PlacementTable::<MatrixEntry>::new(&problem) {
+            Some(table) => run_row_operations(&table, &operations, limit, &mut seed),
+            None => {
+                let table = PlacementTable::<BigInt>::new(&problem).unwrap();
+                run_row_operations(&table, &operations, limit, &mut seed);
+            }
+        }
+        return;
+    }
+
+    if cross_check {
+        cross_validate(&input, copies);
+        return;
+    }
+
+    let solution = solve_problem(&input, copies, method);
     println!("Finished in {:?}", now.elapsed());
-    println!("Solution: {:?}", solution);
+    println!("Solution: {}", solution);
 }
 
 /* --------------- */
@@ -45,6 +140,69 @@ type BlockPositions = Vec<(usize, LocationSet)>;
 
 type MatrixEntry = i128;
 
+// Anything we can count arrangements with. Everything is done with checked arithmetic, so that
+// the counts can be attempted with `i128` first and then redone with `BigInt` on overflow.
+trait Count: Clone + Zero + One + CheckedAdd + CheckedMul {}
+
+impl<T: Clone + Zero + One + CheckedAdd + CheckedMul> Count for T {}
+
+// Walking through the arrangements also needs to compare and subtract counts, and to move
+// indices in and out of big integers (which is how they are given and sampled).
+trait WalkCount: Count + Ord + CheckedSub + Display + Into<BigInt> + TryFrom<BigInt> {}
+
+impl<T: Count + Ord + CheckedSub + Display + Into<BigInt> + TryFrom<BigInt>> WalkCount for T {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CountMethod {
+    Matrices,
+    Periodic,
+    Dp,
+}
+
+// While reading a row one spring at a time, all we need to know is which block is next (mod the
+// number of blocks in a copy, since they repeat), how much of it has been laid down already,
+// and whether the last spring finished off a block (in which case the next must be '.').
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct ReadState {
+    block: usize,
+    progress: u32,
+    needs_gap: bool,
+}
+
+// A polynomial in the "drift", i.e. how many blocks have been completed beyond the number
+// expected so far (a copy of the row needn't hold exactly its own blocks).
+type DriftPoly<T> = BTreeMap<i64, T>;
+
+// How one copy of the row takes each read state to each other, with the drift it introduces
+type Transfer<T> = Vec<Vec<DriftPoly<T>>>;
+
+// The counting structure again, but kept around so that it can be walked: for each block,
+// its possible locations in order and (for every block after the first) the allowance matrix
+// linking it to the block before. `completions[i][j]` counts the ways of placing blocks i
+// onwards given that block i is at its j-th location, so that the total is the sum of
+// `completions[0]`, and any partial placement can be checked for extendability at a glance.
+struct PlacementTable<T> {
+    sizes: Vec<usize>,
+    locations: Vec<Vec<usize>>,
+    matrices: Vec<DMatrix<MatrixEntry>>,
+    completions: Vec<Vec<T>>,
+    total: T,
+    length: usize,
+}
+
+#[derive(Debug, Clone)]
+enum RowOperation {
+    List,
+    Kth(BigInt),
+    Sample(usize),
+}
+
+// Every arrangement, in lexicographic order, produced one at a time
+struct Arrangements<'a, T> {
+    table: &'a PlacementTable<T>,
+    current: Option<Vec<usize>>,
+}
+
 /* ------- */
 /* Parsers */
 /* ------- */
@@ -79,20 +237,124 @@ fn blocks(input: &str) -> IResult<&str, BlockList> {
 /* Logic */
 /* ----- */
 
-fn solve_problem(input: &str) -> MatrixEntry {
+fn solve_problem(input: &str, copies: usize, method: CountMethod) -> BigInt {
+    let (_, problems) = problem_input(input).expect("Failed to parse problem input");
+    let mut total = BigInt::zero();
+    for problem in problems.iter() {
+        total += count_row(problem, copies, method);
+    }
+    total
+}
+
+fn count_row(problem: &SpringProblem, copies: usize, method: CountMethod) -> BigInt {
+    if method == CountMethod::Periodic {
+        return periodic_solutions::<MatrixEntry>(problem, copies)
+            .map(BigInt::from)
+            .unwrap_or_else(|| periodic_solutions::<BigInt>(problem, copies).unwrap());
+    }
+    let mut problem = problem.clone();
+    expand_problem(&mut problem, copies);
+    match method {
+        CountMethod::Dp => dp_solutions::<MatrixEntry>(&problem)
+            .map(BigInt::from)
+            .unwrap_or_else(|| dp_solutions::<BigInt>(&problem).unwrap()),
+        _ => total_solutions::<MatrixEntry>(&problem)
+            .map(BigInt::from)
+            .unwrap_or_else(|| total_solutions::<BigInt>(&problem).unwrap()),
+    }
+}
+
+// Runs the matrix method and the dynamic programming on every row, reporting any rows where
+// they disagree and how long each took in total
+fn cross_validate(input: &str, copies: usize) {
     let (_, problems) = problem_input(input).expect("Failed to parse problem input");
-    let mut total = 0;
-    for mut problem in problems.into_iter() {
-        expand_problem(&mut problem);
-        total += total_solutions(&problem);
+    let mut matrix_time = std::time::Duration::ZERO;
+    let mut dp_time = std::time::Duration::ZERO;
+    let mut matrix_total = BigInt::zero();
+    let mut dp_total = BigInt::zero();
+    let mut mismatches = 0;
+    for (i, problem) in problems.iter().enumerate() {
+        let now = std::time::Instant::now();
+        let by_matrices = count_row(problem, copies, CountMethod::Matrices);
+        matrix_time += now.elapsed();
+        let now = std::time::Instant::now();
+        let by_dp = count_row(problem, copies, CountMethod::Dp);
+        dp_time += now.elapsed();
+
+        if by_matrices != by_dp {
+            mismatches += 1;
+            let blocks: Vec<String> = problem.blocks.iter().map(|b| b.to_string()).collect();
+            println!(
+                "Mismatch on row {} ({} {}): matrices give {}, DP gives {}",
+                i + 1,
+                render_states(&problem.states),
+                blocks.join(","),
+                by_matrices,
+                by_dp
+            );
+        }
+        matrix_total += by_matrices;
+        dp_total += by_dp;
+    }
+    println!("Matrices: {} in {:?}", matrix_total, matrix_time);
+    println!("DP: {} in {:?}", dp_total, dp_time);
+    println!("{} of {} rows disagree", mismatches, problems.len());
+}
+
+// The classic approach: `ways[i][j]` counts the arrangements of the springs from i onwards
+// using the blocks from j onwards. Either spring i is '.', or block j starts there (in which
+// case it must fit before the next '.', and not be followed directly by a '#').
+fn dp_solutions<T: Count>(problem: &SpringProblem) -> Option<T> {
+    let states = &problem.states;
+    let blocks = &problem.blocks;
+    let length = states.len();
+
+    // How far each position is from the next '.'
+    let mut open_run = vec![0; length + 1];
+    for i in (0..length).rev() {
+        if states[i] != State::Okay {
+            open_run[i] = open_run[i + 1] + 1;
+        }
+    }
+
+    let mut ways: Vec<Vec<T>> = vec![vec![T::zero(); blocks.len() + 1]; length + 2];
+    ways[length][blocks.len()] = T::one();
+    ways[length + 1][blocks.len()] = T::one();
+    for i in (0..length).rev() {
+        for j in (0..=blocks.len()).rev() {
+            let mut count = T::zero();
+            if states[i] != State::Broken {
+                count = count.checked_add(&ways[i + 1][j])?;
+            }
+            if states[i] != State::Okay && j < blocks.len() {
+                let size = blocks[j] as usize;
+                let fits = open_run[i] >= size
+                    && (i + size == length || states[i + size] != State::Broken);
+                if fits {
+                    count = count.checked_add(&ways[i + size + 1][j + 1])?;
+                }
+            }
+            ways[i][j] = count;
+        }
     }
-    return total;
+    Some(ways[0][0].clone())
 }
 
-fn expand_problem(problem: &mut SpringProblem) {
+fn render_states(states: &[State]) -> String {
+    states
+        .iter()
+        .map(|state| match state {
+            State::Unknown => '?',
+            State::Broken => '#',
+            State::Okay => '.',
+        })
+        .collect()
+}
+
+fn expand_problem(problem: &mut SpringProblem, copies: usize) {
     let states = problem.states.clone();
     let blocks = problem.blocks.clone();
-    for _i in 0..4 {
+    for _i in 1..copies {
         let mut states_copy = states.clone();
         problem.states.push(State::Unknown);
         problem.states.append(&mut states_copy);
@@ -126,13 +388,15 @@ fn generate_location_sets(spring_problem: &SpringProblem) -> BlockPositions {
         sum -= size + 1;
     }
 
-    // This is just based on theoretical bounds imposed by the block lengths
+    // This is just based on theoretical bounds imposed by the block lengths. When the blocks
+    // can't all fit in the row, some of these are empty (and the row has no arrangements).
     let mut initial_location_sets: BlockPositions = vec![];
     for (idx, size) in blocks.iter().enumerate() {
-        let mut legal_positions: LocationSet = BTreeSet::new();
-        for pos in partial_sums[idx]..=(states_length + 1 - partial_antisums[idx]) {
-            legal_positions.insert(pos);
-        }
+        let legal_positions: LocationSet =
+            match (states_length + 1).checked_sub(partial_antisums[idx]) {
+                Some(last) => (partial_sums[idx]..=last).collect(),
+                None => BTreeSet::new(),
+            };
         initial_location_sets.push((*size as usize, legal_positions));
     }
 
@@ -296,12 +560,45 @@ fn first_and_last_broken(problem: &SpringProblem) -> Option<(usize, usize)> {
     }
 }
 
-fn total_solutions(spring_problem: &SpringProblem) -> MatrixEntry {
+// Rather than multiplying the matrices together, we multiply them into a vector of 1s from
+// the right, one at a time; this is the same as summing the entries of the product, but far
+// cheaper. The intermediate vectors count the ways to complete an arrangement from each
+// location of each block, and are returned in order (None if the counts overflow).
+fn completion_counts<T: Count>(
+    matrices: &[DMatrix<MatrixEntry>],
+    last_locations: usize,
+) -> Option<Vec<Vec<T>>> {
+    let mut completions: Vec<Vec<T>> = vec![vec![T::one(); last_locations]];
+    for matrix in matrices.iter().rev() {
+        let next = completions.last().unwrap();
+        let mut counts: Vec<T> = vec![T::zero(); matrix.ncols()];
+        for (first, count) in counts.iter_mut().enumerate() {
+            for (second, ways) in next.iter().enumerate() {
+                if matrix[(second, first)] == 1 {
+                    *count = count.checked_add(ways)?;
+                }
+            }
+        }
+        completions.push(counts);
+    }
+    completions.reverse();
+    Some(completions)
+}
+
+fn total_solutions<T: Count>(spring_problem: &SpringProblem) -> Option<T> {
     let mut block_positions = generate_location_sets(spring_problem);
+    if block_positions.iter().any(|(_, locs)| locs.is_empty()) {
+        return Some(T::zero());
+    }
     neighbor_prune(&mut block_positions);
+    if block_positions.iter().any(|(_, locs)| locs.is_empty()) {
+        return Some(T::zero());
+    }
     let matrices = causality_matrices(spring_problem, &block_positions);
-    let prod = matrices.into_iter().reduce(|x, y| y * x).unwrap();
-    return prod.sum();
+    let completions = completion_counts::<T>(&matrices, block_positions.last().unwrap().1.len())?;
+    completions[0]
+        .iter()
+        .try_fold(T::zero(), |total, count| total.checked_add(count))
 
     // Morally speaking, the reason this sum works is that the edges of the container
     // also impose constraints, which would contribute a single column and row vector of 1s.
@@ -314,3 +611,404 @@ fn total_solutions(spring_problem: &SpringProblem) -> MatrixEntry {
     // total number of legal assignments; but this representation privileges the first and last
     // block locations for no good reason.
 }
+
+// The unfolded row is `copies` repetitions of the unit (row + '?'), except that the last '?' is
+// dropped (or equivalently, replaced with a '.'). Each unit acts on the read states in the same
+// way, up to drift, so we can find that action once and raise it to a power by repeated
+// squaring. An arrangement is then a path from the initial state back to the start of the
+// block list, with zero drift overall.
+fn periodic_solutions<T: Count>(problem: &SpringProblem, copies: usize) -> Option<T> {
+    let blocks: Vec<u32> = problem.blocks.clone();
+    let mut read_states: Vec<ReadState> = vec![];
+    for (block, size) in blocks.iter().enumerate() {
+        for needs_gap in [false, true] {
+            read_states.push(ReadState {
+                block,
+                progress: 0,
+                needs_gap,
+            });
+        }
+        for progress in 1..*size {
+            read_states.push(ReadState {
+                block,
+                progress,
+                needs_gap: false,
+            });
+        }
+    }
+    let index: HashMap<ReadState, usize> = read_states
+        .iter()
+        .enumerate()
+        .map(|(i, state)| (*state, i))
+        .collect();
+
+    let mut unit = problem.states.clone();
+    unit.push(State::Unknown);
+    let mut last_unit = problem.states.clone();
+    last_unit.push(State::Okay);
+    let step = unit_transfer::<T>(&unit, &blocks, &read_states, &index)?;
+    let last_step = unit_transfer::<T>(&last_unit, &blocks, &read_states, &index)?;
+
+    // Each unit changes the drift by an amount within these bounds, which lets us throw away
+    // any drift that the remaining units couldn't possibly cancel out
+    let drifts = step
+        .iter()
+        .chain(last_step.iter())
+        .flatten()
+        .flat_map(|poly| poly.keys().copied());
+    let (low, high) = drifts.fold((0, 0), |(low, high), d| (low.min(d), high.max(d)));
+    let total_units = copies as i64;
+    let window = |units: i64| {
+        let rest = total_units - units;
+        (
+            (units * low).max(-rest * high),
+            (units * high).min(-rest * low),
+        )
+    };
+
+    // Raise the transfer to the power copies - 1 by repeated squaring
+    let size = read_states.len();
+    let mut result: Transfer<T> = (0..size)
+        .map(|i| {
+            (0..size)
+                .map(|j| {
+                    if i == j {
+                        DriftPoly::from([(0, T::one())])
+                    } else {
+                        DriftPoly::new()
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let mut result_units: i64 = 0;
+    let mut power = step;
+    let mut power_units: i64 = 1;
+    let mut remaining = copies - 1;
+    while remaining > 0 {
+        if remaining % 2 == 1 {
+            result_units += power_units;
+            result = compose(&result, &power, window(result_units))?;
+        }
+        remaining /= 2;
+        if remaining > 0 {
+            power_units *= 2;
+            power = compose(&power, &power, window(power_units))?;
+        }
+    }
+    let full = compose(&result, &last_step, (0, 0))?;
+
+    let start = index[&ReadState {
+        block: 0,
+        progress: 0,
+        needs_gap: false,
+    }];
+    let mut total = T::zero();
+    for needs_gap in [false, true] {
+        let end = index[&ReadState {
+            block: 0,
+            progress: 0,
+            needs_gap,
+        }];
+        if let Some(count) = full[start][end].get(&0) {
+            total = total.checked_add(count)?;
+        }
+    }
+    Some(total)
+}
+
+// Reads one unit spring by spring from each state, recording where it can end up and how many
+// blocks it completed along the way (as drift relative to the number of blocks in a copy)
+fn unit_transfer<T: Count>(
+    unit: &[State],
+    blocks: &[u32],
+    read_states: &[ReadState],
+    index: &HashMap<ReadState, usize>,
+) -> Option<Transfer<T>> {
+    let mut transfer: Transfer<T> =
+        vec![vec![DriftPoly::new(); read_states.len()]; read_states.len()];
+    for (i, start) in read_states.iter().enumerate() {
+        let mut current: HashMap<(ReadState, usize), T> = HashMap::from([((*start, 0), T::one())]);
+        for spring in unit.iter() {
+            let mut next: HashMap<(ReadState, usize), T> = HashMap::new();
+            for ((state, completed), ways) in current.iter() {
+                let mut moves: Vec<(ReadState, usize)> = vec![];
+                if *spring != State::Broken && state.progress == 0 {
+                    moves.push((
+                        ReadState {
+                            needs_gap: false,
+                            ..*state
+                        },
+                        *completed,
+                    ));
+                }
+                if *spring != State::Okay && !state.needs_gap {
+                    if state.progress + 1 == blocks[state.block] {
+                        let following = ReadState {
+                            block: (state.block + 1) % blocks.len(),
+                            progress: 0,
+                            needs_gap: true,
+                        };
+                        moves.push((following, completed + 1));
+                    } else {
+                        let following = ReadState {
+                            progress: state.progress + 1,
+                            ..*state
+                        };
+                        moves.push((following, *completed));
+                    }
+                }
+                for key in moves {
+                    let entry = next.entry(key).or_insert_with(T::zero);
+                    *entry = entry.checked_add(ways)?;
+                }
+            }
+            current = next;
+        }
+        for ((state, completed), ways) in current {
+            let drift = completed as i64 - blocks.len() as i64;
+            transfer[i][index[&state]].insert(drift, ways);
+        }
+    }
+    Some(transfer)
+}
+
+// The product of two transfers, keeping only the drifts within the given window
+fn compose<T: Count>(
+    first: &Transfer<T>,
+    second: &Transfer<T>,
+    (low, high): (i64, i64),
+) -> Option<Transfer<T>> {
+    let size = first.len();
+    let mut product: Transfer<T> = vec![vec![DriftPoly::new(); size]; size];
+    for i in 0..size {
+        for (k, first_poly) in first[i].iter().enumerate() {
+            if first_poly.is_empty() {
+                continue;
+            }
+            for j in 0..size {
+                for (d_1, a) in first_poly.iter() {
+                    for (d_2, b) in second[k][j].iter() {
+                        let drift = d_1 + d_2;
+                        if drift < low || drift > high {
+                            continue;
+                        }
+                        let entry = product[i][j].entry(drift).or_insert_with(T::zero);
+                        *entry = entry.checked_add(&a.checked_mul(b)?)?;
+                    }
+                }
+            }
+        }
+    }
+    Some(product)
+}
+
+impl<T: WalkCount> PlacementTable<T> {
+    // None if the counts overflow `T`
+    fn new(problem: &SpringProblem) -> Option<Self> {
+        let mut block_positions = generate_location_sets(problem);
+        let feasible = block_positions.iter().all(|(_, locs)| !locs.is_empty());
+        if feasible {
+            neighbor_prune(&mut block_positions);
+        }
+        let feasible = feasible && block_positions.iter().all(|(_, locs)| !locs.is_empty());
+        let sizes: Vec<usize> = block_positions.iter().map(|(size, _)| *size).collect();
+        if !feasible {
+            // Some block has nowhere to go, so there's nothing to walk
+            return Some(PlacementTable {
+                locations: vec![vec![]; sizes.len()],
+                completions: vec![vec![]; sizes.len()],
+                total: T::zero(),
+                matrices: vec![],
+                sizes,
+                length: problem.states.len(),
+            });
+        }
+
+        let matrices = causality_matrices(problem, &block_positions);
+        let locations: Vec<Vec<usize>> = block_positions
+            .into_iter()
+            .map(|(_, locs)| locs.into_iter().collect())
+            .collect();
+
+        // Working backwards, each matrix carries the counts for one block to the one before
+        let completions = completion_counts::<T>(&matrices, locations.last().unwrap().len())?;
+        let total = completions[0]
+            .iter()
+            .try_fold(T::zero(), |total, count| total.checked_add(count))?;
+
+        Some(PlacementTable {
+            sizes,
+            locations,
+            matrices,
+            completions,
+            total,
+            length: problem.states.len(),
+        })
+    }
+
+    // The first location (by index) at or after `from` for the given block that follows on
+    // from the previous block's location and can still be completed
+    fn first_extendable(
+        &self,
+        block: usize,
+        previous: Option<usize>,
+        from: usize,
+    ) -> Option<usize> {
+        (from..self.locations[block].len()).find(|j| {
+            self.completions[block][*j] > T::zero()
+                && previous.is_none_or(|p| self.matrices[block - 1][(*j, p)] == 1)
+        })
+    }
+
+    // The k-th arrangement in lexicographic order (of the '#'/'.' strings, which is the same as
+    // the order of the block locations, since '#' < '.'), skipping past whole subtrees by count
+    fn kth(&self, k: &BigInt) -> Option<Vec<usize>> {
+        // Anything in range is below the total, so it fits in `T`
+        let mut remaining = T::try_from(k.clone()).ok()?;
+        if remaining < T::zero() || remaining >= self.total {
+            return None;
+        }
+        let mut choice: Vec<usize> = vec![];
+        for block in 0..self.sizes.len() {
+            let previous = choice.last().copied();
+            let mut j = 0;
+            loop {
+                j = self.first_extendable(block, previous, j)?;
+                let count = &self.completions[block][j];
+                if remaining < *count {
+                    break;
+                }
+                remaining = remaining.checked_sub(count)?;
+                j += 1;
+            }
+            choice.push(j);
+        }
+        Some(choice)
+    }
+
+    fn arrangements(&self) -> Arrangements<'_, T> {
+        Arrangements {
+            table: self,
+            current: None,
+        }
+    }
+
+    fn render(&self, choice: &[usize]) -> String {
+        let mut row = vec!['.'; self.length];
+        for (block, j) in choice.iter().enumerate() {
+            let start = self.locations[block][*j];
+            for spring in row[start..start + self.sizes[block]].iter_mut() {
+                *spring = '#';
+            }
+        }
+        row.into_iter().collect()
+    }
+}
+
+impl<T: WalkCount> Iterator for Arrangements<'_, T> {
+    type Item = String;
+
+    // Moves on to the next arrangement by advancing the last block that can be advanced,
+    // and then placing every block after it as early as possible
+    fn next(&mut self) -> Option<String> {
+        let table = self.table;
+        let blocks = table.sizes.len();
+        let next = match self.current.take() {
+            None => table.kth(&BigInt::zero()),
+            Some(mut choice) => {
+                let advanced = (0..blocks).rev().find_map(|block| {
+                    let previous = if block == 0 {
+                        None
+                    } else {
+                        Some(choice[block - 1])
+                    };
+                    table
+                        .first_extendable(block, previous, choice[block] + 1)
+                        .map(|j| (block, j))
+                });
+                advanced.map(|(block, j)| {
+                    choice[block] = j;
+                    for later in block + 1..blocks {
+                        choice[later] = table
+                            .first_extendable(later, Some(choice[later - 1]), 0)
+                            .unwrap();
+                    }
+                    choice
+                })
+            }
+        };
+        let rendered = next.as_ref().map(|choice| table.render(choice));
+        self.current = next;
+        rendered
+    }
+}
+
+fn run_row_operations<T: WalkCount>(
+    table: &PlacementTable<T>,
+    operations: &[RowOperation],
+    limit: Option<usize>,
+    seed: &mut u64,
+) {
+    for operation in operations {
+        run_row_operation(table, operation, limit, seed);
+    }
+}
+
+fn run_row_operation<T: WalkCount>(
+    table: &PlacementTable<T>,
+    operation: &RowOperation,
+    limit: Option<usize>,
+    seed: &mut u64,
+) {
+    match operation {
+        RowOperation::List => {
+            for arrangement in table.arrangements().take(limit.unwrap_or(usize::MAX)) {
+                println!("{}", arrangement);
+            }
+        }
+        RowOperation::Kth(k) => match table.kth(k) {
+            Some(choice) => println!("{} (arrangement {})", table.render(&choice), k),
+            None => println!("There is no arrangement {} (there are {})", k, table.total),
+        },
+        RowOperation::Sample(count) => {
+            let total: BigInt = table.total.clone().into();
+            if total.is_zero() {
+                println!("There are no arrangements to sample");
+                return;
+            }
+            for _ in 0..*count {
+                let k = random_below(seed, &total);
+                println!(
+                    "{} (arrangement {})",
+                    table.render(&table.kth(&k).unwrap()),
+                    k
+                );
+            }
+        }
+    }
+}
+
+// A uniformly random number in [0, bound) from a little xorshift generator. We draw numbers
+// with as many bits as the bound and reject those that are too big, which avoids any bias
+// (and succeeds at least half the time).
+fn random_below(state: &mut u64, bound: &BigInt) -> BigInt {
+    let mut next = || {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    };
+    let bits = bound.bits();
+    let words = bits.div_ceil(64);
+    loop {
+        let mut draw = BigInt::zero();
+        for _ in 0..words {
+            draw = (draw << 64) + next();
+        }
+        draw >>= words * 64 - bits;
+        if draw < *bound {
+            return draw;
+        }
+    }
+}
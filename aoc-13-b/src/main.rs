@@ -1,13 +1,50 @@
-use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
-    fs,
+use std::{collections::HashMap, env, fs};
+
+// The patterns, their parser and the packed reflection engine are shared with the first part
+use aoc_13::reflections::{
+    find_reflections_packed, problem_input, Axis, CellPair, Element, PackedMap, Reflection, RockMap,
 };
 
 fn main() {
     println!("Let's solve AOC-13!");
     let now = std::time::Instant::now();
+    // `--smudges K` looks for reflections with exactly K smudges instead of one (so Part 1 is
+    // `--smudges 0`), and `--show` prints every reflection line found, with the mismatched
+    // cells and the pattern as it looks with the smudges fixed.
+    // `--hash-map` uses the cell-by-cell comparisons rather than the bitmasks, and
+    // `--bench COUNT SIZE` times the two on COUNT random SIZE x SIZE patterns.
+    let mut smudges = 1;
+    let mut show = false;
+    let mut engine = Engine::Packed;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--smudges" => {
+                let value = args.next().expect("Expected a number after --smudges");
+                smudges = value.parse().expect("Malformed number of smudges");
+            }
+            "--show" => show = true,
+            "--hash-map" => engine = Engine::HashMap,
+            "--bench" => {
+                let mut next_number = || -> usize {
+                    let value = args.next().expect("Usage: --bench COUNT SIZE");
+                    value.parse().expect("Malformed benchmark parameter")
+                };
+                let count = next_number();
+                let size = next_number();
+                assert!(
+                    size >= 2,
+                    "Patterns need a size of at least 2 to have a reflection"
+                );
+                bench(count, size, smudges);
+                return;
+            }
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
     let input = fs::read_to_string("aoc-13-input.txt").expect("Unable to read file");
-    let solution = solve_problem(&input);
+    let solution = solve_problem(&input, smudges, show, engine);
     println!("Elapsed: {:?}", now.elapsed());
     println!("Solution: {}", solution);
 }
@@ -16,265 +53,232 @@ fn main() {
 /* Data Structures */
 /* --------------- */
 
-#[derive(Debug, Clone)]
-struct RockMap {
-    width: usize,
-    height: usize,
-    map: HashMap<(usize, usize), Element>,
-}
-
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum Element {
-    Ash,
-    Rock,
-}
-
-/* ------- */
-/* Parsers */
-/* ------- */
-
-fn problem_input(input: &str) -> Vec<RockMap> {
-    // Things we build up:
-    let mut rock_maps: Vec<RockMap> = vec![];
-    let mut current_map: HashMap<(usize, usize), Element> = HashMap::new();
-
-    // Parser state:
-    let mut cursor = 0;
-    let mut line = 0;
-    let mut width: Option<usize> = None;
-    for c in input.chars() {
-        match c {
-            '\n' => {
-                if cursor == 0 {
-                    let rock_map = RockMap {
-                        width: width.expect("Failed to find width"),
-                        height: line,
-                        map: current_map,
-                    };
-                    rock_maps.push(rock_map);
-                    current_map = HashMap::new();
-                    line = 0;
-                    cursor = 0;
-                    width = None;
-                } else {
-                    line += 1;
-                    if width.is_none() {
-                        width = Some(cursor);
-                    }
-                    cursor = 0;
-                }
-            }
-            '.' => {
-                current_map.insert((cursor, line), Element::Ash);
-                cursor += 1;
-            }
-            '#' => {
-                current_map.insert((cursor, line), Element::Rock);
-                cursor += 1;
-            }
-            _ => {
-                panic!("Illegal character parsed")
-            }
-        }
-    }
-    if !current_map.is_empty() {
-        let rock_map = RockMap {
-            width: width.expect("Failed to find width"),
-            height: line,
-            map: current_map,
-        };
-        rock_maps.push(rock_map);
-    }
-    return rock_maps;
+enum Engine {
+    HashMap,
+    Packed,
 }
 
 /* ----- */
 /* Logic */
 /* ----- */
 
-fn solve_problem(input: &str) -> usize {
+fn solve_problem(input: &str, smudges: usize, show: bool, engine: Engine) -> usize {
     let rock_maps = problem_input(input);
     let mut total = 0;
-    for rock_map in rock_maps.iter() {
-        if let Some(pos) = find_secret_vert_line(rock_map) {
-            total += pos;
-        } else if let Some(pos) = find_secret_horz_line(rock_map) {
-            total += pos * 100;
-        } else {
-            panic!("Didn't solve the problem :(")
+    for (i, rock_map) in rock_maps.iter().enumerate() {
+        let reflections = match engine {
+            Engine::HashMap => find_reflections(rock_map, smudges),
+            Engine::Packed => find_reflections_packed(&PackedMap::new(rock_map), smudges),
+        };
+        if show {
+            println!("Pattern {}:", i + 1);
+            for reflection in reflections.iter() {
+                print_reflection(rock_map, reflection);
+            }
+        }
+        match reflections.first() {
+            Some(reflection) => {
+                if reflections.len() > 1 {
+                    println!(
+                        "Pattern {} has {} reflection lines; counting the first",
+                        i + 1,
+                        reflections.len()
+                    );
+                }
+                total += reflection.score();
+            }
+            None => panic!("Didn't solve the problem :("),
         }
     }
-    return total;
+    total
 }
 
-// Note: The actual input of the problem is such that multiple candidates never actually happens;
-// it's clearly possible to produce examples where there are multiple candidates, but it is unclear
-// to me whether such examples that still have valid solutions exist. Anyway, I added handling for
-// these cases because I could not disprove their existence.
-fn find_secret_vert_line(rock_map: &RockMap) -> Option<usize> {
-    let mut vert_reflection_multiplicities: BTreeMap<usize, usize> = BTreeMap::new();
-    let mut near_reflection_index: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
-    for row in 0..rock_map.height {
-        let (reflections_for_row, near_reflections_for_row) =
-            vert_reflections_in_row(row, rock_map);
-        for pos in near_reflections_for_row {
-            near_reflection_index
-                .entry(pos)
-                .and_modify(|set| {
-                    set.insert(row);
-                })
-                .or_insert([row].into());
-        }
-        for refl in reflections_for_row.iter() {
-            vert_reflection_multiplicities
-                .entry(*refl)
-                .and_modify(|v| *v += 1)
-                .or_insert(1);
-        }
-    }
-    let mut candidates: Vec<usize> = vec![];
-    for (pos, mult) in vert_reflection_multiplicities.iter() {
-        if *mult == rock_map.height - 1 {
-            candidates.push(*pos);
-        }
-    }
-    if candidates.len() == 1 {
-        return Some(*candidates.first().unwrap());
-    } else if candidates.len() == 0 {
-        return None;
-    }
-    // We found more than one candidate, but at most one is actually near a row;
-    // we locate it with the index. If it doesn't exist, then we didn't find anything
-    else {
-        for pos in candidates.iter() {
-            match near_reflection_index.get(pos) {
-                Some(set) => {
-                    if !set.is_empty() {
-                        return Some(*pos);
-                    } else {
-                        continue;
-                    }
-                }
-                None => {
-                    continue;
+// The same search as `find_reflections_packed`, but going cell by cell through the hash map
+fn find_reflections(rock_map: &RockMap, smudges: usize) -> Vec<Reflection> {
+    let mut reflections = vec![];
+    for axis in [Axis::Vertical, Axis::Horizontal] {
+        let (across, along) = match axis {
+            Axis::Vertical => (rock_map.width, rock_map.height),
+            Axis::Horizontal => (rock_map.height, rock_map.width),
+        };
+        for position in 1..across {
+            if let Some(mismatches) =
+                reflection_mismatches(rock_map, axis, position, along, smudges)
+            {
+                if mismatches.len() == smudges {
+                    reflections.push(Reflection {
+                        axis,
+                        position,
+                        mismatches,
+                    });
                 }
             }
         }
-        return None;
     }
+    reflections
 }
 
-fn find_secret_horz_line(rock_map: &RockMap) -> Option<usize> {
-    let mut horz_reflection_multiplicities: BTreeMap<usize, usize> = BTreeMap::new();
-    let mut near_reflection_index: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
-    for column in 0..rock_map.width {
-        let (reflections_for_column, near_reflections_for_column) =
-            horz_reflections_in_column(column, rock_map);
-        for pos in near_reflections_for_column {
-            near_reflection_index
-                .entry(pos)
-                .and_modify(|set| {
-                    set.insert(column);
-                })
-                .or_insert([column].into());
-        }
-        for refl in reflections_for_column.iter() {
-            horz_reflection_multiplicities
-                .entry(*refl)
-                .and_modify(|v| *v += 1)
-                .or_insert(1);
-        }
-    }
-    let mut candidates: Vec<usize> = vec![];
-    for (pos, mult) in horz_reflection_multiplicities.iter() {
-        if *mult == rock_map.width - 1 {
-            candidates.push(*pos);
-        }
-    }
-    if candidates.len() == 1 {
-        return Some(*candidates.first().unwrap());
-    } else if candidates.len() == 0 {
-        return None;
-    } else {
-        for pos in candidates.iter() {
-            match near_reflection_index.get(pos) {
-                Some(set) => {
-                    if !set.is_empty() {
-                        return Some(*pos);
-                    } else {
-                        continue;
-                    }
-                }
-                None => {
-                    continue;
+// Compares each line before `position` with its mirror image after it, collecting the pairs of
+// cells that differ; gives up once there are more than `limit` of them
+fn reflection_mismatches(
+    rock_map: &RockMap,
+    axis: Axis,
+    position: usize,
+    along: usize,
+    limit: usize,
+) -> Option<Vec<CellPair>> {
+    let across = match axis {
+        Axis::Vertical => rock_map.width,
+        Axis::Horizontal => rock_map.height,
+    };
+    let mut mismatches = vec![];
+    for inc in 0..position.min(across - position) {
+        for other in 0..along {
+            let (near, far) = match axis {
+                Axis::Vertical => ((position - 1 - inc, other), (position + inc, other)),
+                Axis::Horizontal => ((other, position - 1 - inc), (other, position + inc)),
+            };
+            if rock_map.map[&near] != rock_map.map[&far] {
+                mismatches.push((near, far));
+                if mismatches.len() > limit {
+                    return None;
                 }
             }
         }
-        return None;
     }
+    Some(mismatches)
 }
 
-// These functions tell us how close each potential reflection is to being a reflection;
-// i.e. how many symbols would have to change in order to make the position one of reflection
-fn vert_reflection_dist(position: usize, row: usize, rock_map: &RockMap) -> usize {
-    let mut distance = 0;
-    for (inc, idx) in (0..position).rev().enumerate() {
-        let left_guy = rock_map.map.get(&(idx, row)).unwrap(); // Guaranteed to exist
-        if position + inc < rock_map.width {
-            let right_guy = rock_map.map.get(&(position + inc, row)).unwrap(); // We are in bounds
-            if !(*left_guy == *right_guy) {
-                distance += 1;
-                continue;
-            } else {
-                continue;
+// Times both engines on random patterns, each with a reflection planted somewhere along with
+// the given number of smudges, using a little xorshift generator so that runs are reproducible.
+fn bench(count: usize, size: usize, smudges: usize) {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut random = move |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+
+    let mut rock_maps = vec![];
+    for _ in 0..count {
+        let axis = if random(2) == 0 {
+            Axis::Vertical
+        } else {
+            Axis::Horizontal
+        };
+        let position = 1 + random(size - 1);
+        let mut map = HashMap::new();
+        for y in 0..size {
+            for x in 0..size {
+                // Read the cell across the line, if there is one, so that the pattern reflects
+                let (along, across) = match axis {
+                    Axis::Vertical => (y, x),
+                    Axis::Horizontal => (x, y),
+                };
+                let mirrored = if across >= position && 2 * position > across {
+                    let image = 2 * position - 1 - across;
+                    Some(match axis {
+                        Axis::Vertical => (image, along),
+                        Axis::Horizontal => (along, image),
+                    })
+                } else {
+                    None
+                };
+                let element = match mirrored.map(|cell| map[&cell]) {
+                    Some(element) => element,
+                    None if random(2) == 0 => Element::Ash,
+                    None => Element::Rock,
+                };
+                map.insert((x, y), element);
             }
         }
-        // We made it to the end
-        else {
-            return distance;
+        let mut rock_map = RockMap {
+            width: size,
+            height: size,
+            map,
+        };
+
+        // Smudge cells in the mirrored part of the pattern, if there are enough of them
+        let reach = position.min(size - position);
+        let mut smudged: Vec<(usize, usize)> = vec![];
+        while smudged.len() < smudges.min(reach * size) {
+            let across = position + random(reach);
+            let along = random(size);
+            let cell = match axis {
+                Axis::Vertical => (across, along),
+                Axis::Horizontal => (along, across),
+            };
+            if !smudged.contains(&cell) {
+                smudged.push(cell);
+                let element = rock_map.map.get_mut(&cell).unwrap();
+                *element = match element {
+                    Element::Ash => Element::Rock,
+                    Element::Rock => Element::Ash,
+                };
+            }
         }
+        rock_maps.push(rock_map);
     }
-    return distance;
+
+    let now = std::time::Instant::now();
+    let by_hash_map: Vec<Vec<Reflection>> = rock_maps
+        .iter()
+        .map(|rock_map| find_reflections(rock_map, smudges))
+        .collect();
+    println!("Hash map: {:?}", now.elapsed());
+
+    let now = std::time::Instant::now();
+    let by_packing: Vec<Vec<Reflection>> = rock_maps
+        .iter()
+        .map(|rock_map| find_reflections_packed(&PackedMap::new(rock_map), smudges))
+        .collect();
+    println!("Packed (including packing): {:?}", now.elapsed());
+
+    assert_eq!(by_hash_map, by_packing, "The two engines disagree");
+    let found: usize = by_packing.iter().map(|r| r.len()).sum();
+    println!("Both found the same {} reflection lines", found);
 }
 
-fn horz_reflection_dist(position: usize, column: usize, rock_map: &RockMap) -> usize {
-    let mut distance = 0;
-    for (inc, idx) in (0..position).rev().enumerate() {
-        let top_guy = rock_map.map.get(&(column, idx)).unwrap(); // Guaranteed to exist
-        if position + inc < rock_map.height {
-            let bottom_guy = rock_map.map.get(&(column, position + inc)).unwrap(); // We are in bounds
-            if !(*top_guy == *bottom_guy) {
-                distance += 1;
-                continue;
-            } else {
-                continue;
-            }
-        }
-        // We made it to the end
-        else {
-            return distance;
-        }
+// Fixes the smudges by flipping the first cell of each mismatched pair, so that the
+// reflection becomes exact
+fn corrected(rock_map: &RockMap, reflection: &Reflection) -> RockMap {
+    let mut fixed = rock_map.clone();
+    for (near, far) in reflection.mismatches.iter() {
+        let other = fixed.map[far];
+        fixed.map.insert(*near, other);
     }
-    return distance;
+    fixed
 }
 
-// These functions return a pair; the first element is the set of legal reflections, while the second
-// is the set of near-reflections (i.e. those with distance 1).
-fn vert_reflections_in_row(row: usize, rock_map: &RockMap) -> (BTreeSet<usize>, BTreeSet<usize>) {
-    let mut near_reflections: BTreeSet<usize> = (1..rock_map.width).collect();
-    let mut reflections: BTreeSet<usize> = (1..rock_map.width).collect();
-    near_reflections.retain(|pos| vert_reflection_dist(*pos, row, rock_map) == 1);
-    reflections.retain(|pos| vert_reflection_dist(*pos, row, rock_map) == 0);
-    return (reflections, near_reflections);
+fn render(rock_map: &RockMap) -> String {
+    let mut text = String::new();
+    for y in 0..rock_map.height {
+        for x in 0..rock_map.width {
+            text.push(match rock_map.map[&(x, y)] {
+                Element::Ash => '.',
+                Element::Rock => '#',
+            });
+        }
+        text.push('\n');
+    }
+    text
 }
 
-fn horz_reflections_in_column(
-    column: usize,
-    rock_map: &RockMap,
-) -> (BTreeSet<usize>, BTreeSet<usize>) {
-    let mut near_reflections: BTreeSet<usize> = (1..rock_map.height).collect();
-    let mut reflections: BTreeSet<usize> = (1..rock_map.height).collect();
-    near_reflections.retain(|pos| horz_reflection_dist(*pos, column, rock_map) == 1);
-    reflections.retain(|pos| horz_reflection_dist(*pos, column, rock_map) == 0);
-    return (reflections, near_reflections);
+fn print_reflection(rock_map: &RockMap, reflection: &Reflection) {
+    let kind = match reflection.axis {
+        Axis::Vertical => "Vertical line after column",
+        Axis::Horizontal => "Horizontal line after row",
+    };
+    let mismatches: Vec<String> = reflection
+        .mismatches
+        .iter()
+        .map(|(near, far)| format!("{:?} vs {:?}", near, far))
+        .collect();
+    println!("{} {}", kind, reflection.position);
+    if !mismatches.is_empty() {
+        println!("Mismatches: {}", mismatches.join(", "));
+        print!("{}", render(&corrected(rock_map, reflection)));
+    }
 }
@@ -0,0 +1,3 @@
+// The second part depends on this crate for the reflection engine, so it lives in a library
+// alongside the binary
+pub mod reflections;
@@ -0,0 +1,189 @@
+// Patterns of ash and rocks, their parser, and the packed reflection engine, shared between
+// both parts of the day
+
+use std::collections::HashMap;
+
+/* --------------- */
+/* Data Structures */
+/* --------------- */
+
+#[derive(Debug, Clone)]
+pub struct RockMap {
+    pub width: usize,
+    pub height: usize,
+    pub map: HashMap<(usize, usize), Element>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Element {
+    Ash,
+    Rock,
+}
+
+// A vertical line of reflection lies between two columns, and a horizontal one between two rows
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Axis {
+    Vertical,
+    Horizontal,
+}
+
+// Each row and each column as a bitmask (with rocks as 1s) split into 64-bit words, so that
+// the number of differences between two lines is the popcount of their XOR
+#[derive(Debug, Clone)]
+pub struct PackedMap {
+    rows: Vec<Vec<u64>>,
+    columns: Vec<Vec<u64>>,
+}
+
+// Two cells (as (x, y)) on opposite sides of a line of reflection
+pub type CellPair = ((usize, usize), (usize, usize));
+
+// A line of reflection, given by the number of columns (or rows) before it, along with the
+// pairs of mirrored cells that don't actually match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reflection {
+    pub axis: Axis,
+    pub position: usize,
+    pub mismatches: Vec<CellPair>,
+}
+
+impl PackedMap {
+    pub fn new(rock_map: &RockMap) -> Self {
+        let words = |bits: usize| bits.div_ceil(64);
+        let mut rows = vec![vec![0u64; words(rock_map.width)]; rock_map.height];
+        let mut columns = vec![vec![0u64; words(rock_map.height)]; rock_map.width];
+        for ((x, y), element) in rock_map.map.iter() {
+            if *element == Element::Rock {
+                rows[*y][x / 64] |= 1 << (x % 64);
+                columns[*x][y / 64] |= 1 << (y % 64);
+            }
+        }
+        PackedMap { rows, columns }
+    }
+}
+
+impl Reflection {
+    pub fn score(&self) -> usize {
+        match self.axis {
+            Axis::Vertical => self.position,
+            Axis::Horizontal => self.position * 100,
+        }
+    }
+}
+
+/* ------- */
+/* Parsers */
+/* ------- */
+
+pub fn problem_input(input: &str) -> Vec<RockMap> {
+    // Things we build up:
+    let mut rock_maps: Vec<RockMap> = vec![];
+    let mut current_map: HashMap<(usize, usize), Element> = HashMap::new();
+
+    // Parser state:
+    let mut cursor = 0;
+    let mut line = 0;
+    let mut width: Option<usize> = None;
+    for c in input.chars() {
+        match c {
+            '\n' => {
+                if cursor == 0 {
+                    let rock_map = RockMap {
+                        width: width.expect("Failed to find width"),
+                        height: line,
+                        map: current_map,
+                    };
+                    rock_maps.push(rock_map);
+                    current_map = HashMap::new();
+                    line = 0;
+                    cursor = 0;
+                    width = None;
+                } else {
+                    line += 1;
+                    if width.is_none() {
+                        width = Some(cursor);
+                    }
+                    cursor = 0;
+                }
+            }
+            '.' => {
+                current_map.insert((cursor, line), Element::Ash);
+                cursor += 1;
+            }
+            '#' => {
+                current_map.insert((cursor, line), Element::Rock);
+                cursor += 1;
+            }
+            _ => {
+                panic!("Illegal character parsed")
+            }
+        }
+    }
+    if !current_map.is_empty() {
+        let rock_map = RockMap {
+            width: width.expect("Failed to find width"),
+            height: line,
+            map: current_map,
+        };
+        rock_maps.push(rock_map);
+    }
+    return rock_maps;
+}
+
+/* ----- */
+/* Logic */
+/* ----- */
+
+// Every line of reflection (vertical ones first) for which exactly `smudges` cells are out of
+// place, comparing whole lines at once; a vertical line of reflection compares columns, and a
+// horizontal one compares rows. Part 1 is `smudges` = 0, and Part 2 is `smudges` = 1.
+pub fn find_reflections_packed(packed: &PackedMap, smudges: usize) -> Vec<Reflection> {
+    let mut reflections = vec![];
+    for axis in [Axis::Vertical, Axis::Horizontal] {
+        let lines = match axis {
+            Axis::Vertical => &packed.columns,
+            Axis::Horizontal => &packed.rows,
+        };
+        'position: for position in 1..lines.len() {
+            // Count first, and only work out where the mismatches are if there are few enough
+            let mut distance = 0;
+            for inc in 0..position.min(lines.len() - position) {
+                distance += line_distance(&lines[position - 1 - inc], &lines[position + inc]);
+                if distance > smudges {
+                    continue 'position;
+                }
+            }
+            if distance != smudges {
+                continue;
+            }
+            let mut mismatches = vec![];
+            for inc in 0..position.min(lines.len() - position) {
+                let (near, far) = (position - 1 - inc, position + inc);
+                for (w, (a, b)) in lines[near].iter().zip(lines[far].iter()).enumerate() {
+                    let mut diff = a ^ b;
+                    while diff != 0 {
+                        let other = w * 64 + diff.trailing_zeros() as usize;
+                        diff &= diff - 1;
+                        mismatches.push(match axis {
+                            Axis::Vertical => ((near, other), (far, other)),
+                            Axis::Horizontal => ((other, near), (other, far)),
+                        });
+                    }
+                }
+            }
+            reflections.push(Reflection {
+                axis,
+                position,
+                mismatches,
+            });
+        }
+    }
+    reflections
+}
+
+fn line_distance(a: &[u64], b: &[u64]) -> usize {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x ^ y).count_ones() as usize)
+        .sum()
+}
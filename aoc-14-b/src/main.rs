@@ -1,56 +1,89 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    fs,
+use nom::{
+    character::complete::{char, one_of, u64},
+    combinator::{all_consuming, map},
+    multi::{many1, separated_list1},
+    IResult,
 };
+use std::{collections::HashMap, env, fs};
 
 fn main() {
     println!("Let's solve AOC-14!");
     let now = std::time::Instant::now();
     let input = fs::read_to_string("aoc-14-input.txt").expect("Unable to read file");
-    let solution = solve_problem(&input);
+    // `--program TILTS` sets the sequence of tilts making up one iteration (by default `NWSE`,
+    // the spin cycle) and `--iterations N` how many iterations to run, so Part 1 is
+    // `--program N --iterations 1`; given a list like `--iterations 1,10,1000` it reports the
+    // load after each of them, with the last one as the solution.
+    // `--cycle` reports where the states start repeating and the loads on every side of the
+    // platform over one period, and `--render N` (which can be repeated) prints the platform
+    // after N iterations.
+    let mut program = vec![Direction::N, Direction::W, Direction::S, Direction::E];
+    let mut targets = vec![MAX_ITERATIONS];
+    let mut show_cycle = false;
+    let mut renders: Vec<usize> = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--program" => {
+                let value = args
+                    .next()
+                    .expect("Expected a sequence of tilts after --program");
+                let (_, tilts) =
+                    tilt_program(&value).expect("Malformed program (tilts are N, S, E or W)");
+                program = tilts;
+            }
+            "--iterations" => {
+                let value = args.next().expect("Expected a number after --iterations");
+                let (_, counts) = iteration_list(&value).expect("Malformed number of iterations");
+                targets = counts;
+            }
+            "--cycle" => show_cycle = true,
+            "--render" => {
+                let value = args.next().expect("Expected a number after --render");
+                renders.push(value.parse().expect("Malformed number of iterations"));
+            }
+            _ => panic!("Unrecognised argument: {arg}"),
+        }
+    }
+
+    let solution = solve_problem(&input, &program, &targets, show_cycle, &renders);
     println!("Elapsed: {:?}", now.elapsed());
     println!("Solution: {}", solution);
 }
 
-const MAX_ITERATIONS: isize = 1000000000;
+const MAX_ITERATIONS: usize = 1000000000;
 
 /* --------------- */
 /* Data Structures */
 /* --------------- */
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-enum Rock {
-    Round,
-    Square,
+
+// Each row of the platform is a bitmask with column `c` at bit `c`, kept separately for the
+// round rocks (which move) and the cube rocks (which don't). Tilting north or south moves
+// whole rows of rocks at once, and tilting east or west only ever moves bits within a row,
+// so a tilt is a few word operations per row instead of a walk over every rock.
+// Rows are single words, so the platform can be at most 128 columns wide.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Platform {
+    width: usize,
+    height: usize,
+    cubes: Vec<u128>,
+    rounds: Vec<u128>,
 }
 
-// Now, we doubly record the map data; BTreeMap uses lexicographic sort order,
-// so one of these will be sorted column-first and the other will be sorted
-// row-first. We can update them in tandem, but this makes it easier to do the
-// north/south/east/west transformations because it makes it trivial to iterate
-// along a single row or column.
-#[derive(Clone, PartialEq, Eq, Hash)]
-struct RockMap {
-    width: isize,
-    height: isize,
-    map_by_cols: BTreeMap<(isize, isize), Rock>,
-    map_by_rows: BTreeMap<(isize, isize), Rock>,
+// Every state the platform went through, as its round rocks: `states[i]` is the state after
+// `i` iterations. We only simulate until the first repeat, if we get that far, and after that
+// the states run through the same `period` over and over, starting from iteration `prefix`.
+#[derive(Clone, Debug)]
+struct History {
+    platform: Platform,
+    states: Vec<Vec<u128>>,
+    cycle: Option<Cycle>,
 }
 
-impl RockMap {
-    // Removes a rock at `old_coords` and places one at `new_coords`
-    // `transposed` dictates whether the inputs to this are in transposed coordinates
-    fn alter(&mut self, transposed: bool, old_coords: (isize, isize), new_coords: (isize, isize)) {
-        let (mut old_x, mut old_y) = old_coords;
-        let (mut new_x, mut new_y) = new_coords;
-        if transposed {
-            (old_x, old_y) = (old_y, old_x);
-            (new_x, new_y) = (new_y, new_x);
-        }
-        self.map_by_cols.remove(&(old_x, old_y));
-        self.map_by_rows.remove(&(old_y, old_x));
-        self.map_by_cols.insert((new_x, new_y), Rock::Round);
-        self.map_by_rows.insert((new_y, new_x), Rock::Round);
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cycle {
+    prefix: usize,
+    period: usize,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -61,45 +94,39 @@ enum Direction {
     W,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Directionality {
-    Forward,
-    Backward,
-}
-
 /* ------- */
 /* Parsers */
 /* ------- */
 
-fn problem_input(input: &str) -> RockMap {
+fn problem_input(input: &str) -> Platform {
     // Things we build up:
-    let mut map_cols: BTreeMap<(isize, isize), Rock> = BTreeMap::new();
-    let mut map_rows: BTreeMap<(isize, isize), Rock> = BTreeMap::new();
+    let mut cubes: Vec<u128> = Vec::new();
+    let mut rounds: Vec<u128> = Vec::new();
 
     // Parser state:
     let mut cursor = 0;
-    let mut line = 0;
-    let mut width: Option<isize> = None;
+    let mut row_cubes: u128 = 0;
+    let mut row_rounds: u128 = 0;
+    let mut width: Option<usize> = None;
     for c in input.chars() {
         match c {
             '\n' => {
-                line += 1;
+                cubes.push(row_cubes);
+                rounds.push(row_rounds);
                 if width.is_none() {
                     width = Some(cursor);
                 }
                 cursor = 0;
+                row_cubes = 0;
+                row_rounds = 0;
             }
-            'O' => {
-                map_cols.insert((cursor, line), Rock::Round);
-                map_rows.insert((line, cursor), Rock::Round);
-                cursor += 1;
-            }
-            '#' => {
-                map_cols.insert((cursor, line), Rock::Square);
-                map_rows.insert((line, cursor), Rock::Square);
-                cursor += 1;
-            }
-            '.' => {
+            'O' | '#' | '.' => {
+                assert!(cursor < 128, "Platform is too wide (at most 128 columns)");
+                match c {
+                    'O' => row_rounds |= 1 << cursor,
+                    '#' => row_cubes |= 1 << cursor,
+                    _ => {}
+                }
                 cursor += 1;
             }
             _ => {
@@ -107,167 +134,247 @@ fn problem_input(input: &str) -> RockMap {
             }
         }
     }
-    return RockMap {
+    Platform {
         width: width.expect("Failed to find width"),
-        height: line,
-        map_by_cols: map_cols,
-        map_by_rows: map_rows,
-    };
+        height: rounds.len(),
+        cubes,
+        rounds,
+    }
+}
+
+fn tilt_program(input: &str) -> IResult<&str, Vec<Direction>> {
+    all_consuming(many1(map(one_of("NSEW"), |c| match c {
+        'N' => Direction::N,
+        'S' => Direction::S,
+        'E' => Direction::E,
+        _ => Direction::W,
+    })))(input)
+}
+
+fn iteration_list(input: &str) -> IResult<&str, Vec<usize>> {
+    all_consuming(separated_list1(char(','), map(u64, |n| n as usize)))(input)
 }
 
 /* ----- */
 /* Logic */
 /* ----- */
 
-fn solve_problem(input: &str) -> isize {
-    let mut rock_map = problem_input(input);
-    let mut iterations = 0;
-    let mut visited: HashMap<RockMap, isize> = HashMap::new();
-    let mut additional_iterations: Option<isize> = None;
-
-    // We just start looping our guy and recording all the previous states in a HashMap
-    // together with the iteration where they occurred
-    loop {
-        cycle(&mut rock_map);
-        iterations += 1;
+fn solve_problem(
+    input: &str,
+    program: &[Direction],
+    targets: &[usize],
+    show_cycle: bool,
+    renders: &[usize],
+) -> usize {
+    let platform = problem_input(input);
+    // There are only finitely many states, so asking for the cycle means running until the
+    // first repeat, however few iterations the targets need
+    let limit = if show_cycle {
+        usize::MAX
+    } else {
+        targets.iter().chain(renders).copied().max().unwrap_or(0)
+    };
+    let history = simulate(&platform, program, limit);
 
-        // If we visited this state before, we compute the length of the cycle; then
-        // we break out so we can iterate a little more until we agree with `MAX_ITERATIONS`
-        // modulo the cycle length of the dynamics.
-        if visited.contains_key(&rock_map) {
-            let last_visited = visited.get(&rock_map).unwrap();
-            let length = iterations - last_visited;
-            println!(
-                "Cycle length: {}, observed at iteration {}",
-                length, iterations
-            );
-            additional_iterations = Some((MAX_ITERATIONS - iterations) % length);
-            break;
-        } else {
-            visited.insert(rock_map.clone(), iterations);
+    if show_cycle {
+        print_cycle(&history);
+    }
+    for &iteration in renders {
+        println!("After {} iterations:", iteration);
+        print!("{}", history.platform_at(iteration).render());
+    }
+    let loads: Vec<usize> = targets
+        .iter()
+        .map(|&iteration| history.platform_at(iteration).load(Direction::N))
+        .collect();
+    if targets.len() > 1 {
+        for (iteration, load) in targets.iter().zip(&loads) {
+            println!("After {} iterations: north load {}", iteration, load);
         }
+    }
+    *loads.last().expect("No iteration counts given")
+}
 
-        // I put this here just so that this theoretically always terminates, even if
-        // the runtime would be completely heinous
-        if iterations == MAX_ITERATIONS {
+// Runs `program` on the platform until it has been run `limit` times or a state repeats,
+// whichever comes first. The cube rocks never move, so the state is just the round rocks;
+// we record every state in a HashMap together with the iteration where it occurred, so that
+// a repeat tells us both where the cycle starts and how long it is.
+fn simulate(platform: &Platform, program: &[Direction], limit: usize) -> History {
+    let mut current = platform.clone();
+    let mut states: Vec<Vec<u128>> = vec![current.rounds.clone()];
+    let mut visited: HashMap<Vec<u128>, usize> = HashMap::from([(current.rounds.clone(), 0)]);
+    let mut cycle = None;
+    for iteration in 1..=limit {
+        for &direction in program {
+            current.tilt(direction);
+        }
+        if let Some(&prefix) = visited.get(&current.rounds) {
+            cycle = Some(Cycle {
+                prefix,
+                period: iteration - prefix,
+            });
             break;
         }
+        visited.insert(current.rounds.clone(), iteration);
+        states.push(current.rounds.clone());
+    }
+    History {
+        platform: platform.clone(),
+        states,
+        cycle,
     }
+}
 
-    // We have some remaining iterations to perform to reach the right state in the cycle
-    if let Some(mut remaining_iterations) = additional_iterations {
-        while remaining_iterations > 0 {
-            cycle(&mut rock_map);
-            remaining_iterations -= 1;
+impl History {
+    // Past the recorded states, the state is determined by where we are in the cycle
+    fn platform_at(&self, iteration: usize) -> Platform {
+        let index = match self.cycle {
+            Some(Cycle { prefix, period }) if iteration >= self.states.len() => {
+                prefix + (iteration - prefix) % period
+            }
+            _ => iteration,
+        };
+        Platform {
+            rounds: self.states[index].clone(),
+            ..self.platform.clone()
         }
     }
-    return total_load(&rock_map);
 }
 
-// All of the shift operations have the same idea, so I tried to reuse code, but it only sort of worked,
-// since the iterator's type changes depending on whether you reverse it, which I hadn't accounted for
-// at the outset.
-fn shift(rock_map: &mut RockMap, direction: Direction) {
-    let (map_to_use, directionality, transposed, boundary, orthog_max) = match direction {
-        Direction::N => (
-            rock_map.map_by_cols.clone(),
-            Directionality::Backward,
-            false,
-            rock_map.height,
-            rock_map.width,
-        ),
-        Direction::S => (
-            rock_map.map_by_cols.clone(),
-            Directionality::Forward,
-            false,
-            rock_map.height,
-            rock_map.width,
-        ),
-        Direction::E => (
-            rock_map.map_by_rows.clone(),
-            Directionality::Forward,
-            true,
-            rock_map.width,
-            rock_map.height,
-        ),
-        Direction::W => (
-            rock_map.map_by_rows.clone(),
-            Directionality::Backward,
-            true,
-            rock_map.width,
-            rock_map.height,
-        ),
+fn print_cycle(history: &History) {
+    let Some(Cycle { prefix, period }) = history.cycle else {
+        println!(
+            "No repeated state within {} iterations",
+            history.states.len() - 1
+        );
+        return;
     };
+    println!("Prefix length: {}, period: {}", prefix, period);
+    for iteration in prefix..prefix + period {
+        let platform = history.platform_at(iteration);
+        println!(
+            "Iteration {}: north load {}, south load {}, east load {}, west load {}",
+            iteration,
+            platform.load(Direction::N),
+            platform.load(Direction::S),
+            platform.load(Direction::E),
+            platform.load(Direction::W),
+        );
+    }
+}
 
-    // Outer iterator moves over lines in the direction orthogonal to the direction where rocks roll
-    for line in 0..orthog_max {
-        let iterator = map_to_use.range((line, 0)..(line + 1, 0));
-        match directionality {
-            Directionality::Backward => {
-                // `resting_place` records the position of where the next Round rock we encounter
-                // should roll.
-                let mut resting_place = 0;
-                for (coords, rock) in iterator {
-                    match rock {
-                        // When we encounter a round rock, we move it to the `resting_place`;
-                        // it takes up space, so the `resting_place` moves by one position
-                        Rock::Round => {
-                            rock_map.alter(transposed, *coords, (line, resting_place));
-                            resting_place += 1;
-                        }
+// The bits below position `n`
+fn low_mask(n: usize) -> u128 {
+    if n >= 128 {
+        u128::MAX
+    } else {
+        (1 << n) - 1
+    }
+}
 
-                        // When we encounter a Square rock, we update our `resting_place` so
-                        // that it is next to it.
-                        Rock::Square => {
-                            let (_ln, pos) = coords;
-                            resting_place = pos + 1;
-                        }
-                    }
-                }
-            }
-            Directionality::Forward => {
-                let mut resting_place = boundary - 1;
-                for (coords, rock) in iterator.rev() {
-                    match rock {
-                        Rock::Round => {
-                            rock_map.alter(transposed, *coords, (line, resting_place));
-                            resting_place -= 1;
-                        }
-                        Rock::Square => {
-                            let (_ln, pos) = coords;
-                            resting_place = pos - 1;
-                        }
-                    }
-                }
+impl Platform {
+    fn tilt(&mut self, direction: Direction) {
+        match direction {
+            Direction::N => self.tilt_vertical(true),
+            Direction::S => self.tilt_vertical(false),
+            Direction::W => self.tilt_horizontal(true),
+            Direction::E => self.tilt_horizontal(false),
+        }
+    }
+
+    // Moves the rocks in `moving` (all of which are in row `from`) into row `to` wherever
+    // there is space, and returns the ones that moved
+    fn slide_rows(&mut self, moving: u128, from: usize, to: usize) -> u128 {
+        let moved = moving & !(self.rounds[to] | self.cubes[to]);
+        self.rounds[from] &= !moved;
+        self.rounds[to] |= moved;
+        moved
+    }
+
+    // We settle the rows starting from the side we are tilting towards; by the time we get to
+    // a row, everything in front of it is already in place, so its rocks (in every column at
+    // once) just keep sliding forward a row at a time until none of them can move any further.
+    fn tilt_vertical(&mut self, north: bool) {
+        for row in 0..self.height {
+            let mut current = if north { row } else { self.height - 1 - row };
+            let mut moving = self.rounds[current];
+            while moving != 0 {
+                let next = match (north, current) {
+                    (true, 0) => break,
+                    (true, _) => current - 1,
+                    (false, _) if current + 1 == self.height => break,
+                    (false, _) => current + 1,
+                };
+                moving = self.slide_rows(moving, current, next);
+                current = next;
             }
         }
     }
-}
 
-// Run one cycle
-fn cycle(rock_map: &mut RockMap) {
-    shift(rock_map, Direction::N);
-    shift(rock_map, Direction::W);
-    shift(rock_map, Direction::S);
-    shift(rock_map, Direction::E);
-}
+    // Within a row, the stretches between cube rocks (and the edges) are independent, and
+    // all the round rocks in a stretch just pile up at one end of it
+    fn tilt_horizontal(&mut self, west: bool) {
+        for row in 0..self.height {
+            let cubes = self.cubes[row];
+            let rounds = self.rounds[row];
+            let mut packed = 0;
+            let mut start = 0;
+            while start < self.width {
+                let ahead = cubes & !low_mask(start);
+                let end = if ahead == 0 {
+                    self.width
+                } else {
+                    ahead.trailing_zeros() as usize
+                };
+                let stretch = low_mask(end) & !low_mask(start);
+                let count = (rounds & stretch).count_ones() as usize;
+                packed |= if west {
+                    low_mask(start + count) & !low_mask(start)
+                } else {
+                    low_mask(end) & !low_mask(end - count)
+                };
+                start = end + 1;
+            }
+            self.rounds[row] = packed;
+        }
+    }
 
-// Instead of pre-accounting for a transformation, we just operate on the
-// transformed version of the map, so this part becomes boring and trivial
-fn column_sum(column: isize, rock_map: &RockMap) -> isize {
-    let this_column = (column, 0)..(column + 1, 0);
-    let height: isize = rock_map.height;
-    let mut total = 0;
-    for ((_, pos), rock) in rock_map.map_by_cols.range(this_column) {
-        if *rock == Rock::Round {
-            total += height - *pos;
+    // Each round rock contributes its distance to the opposite edge, counting its own row
+    // (or column); so for the north beam, the number of rows from it to the south edge
+    fn load(&self, beam: Direction) -> usize {
+        let mut total = 0;
+        for (row, &rounds) in self.rounds.iter().enumerate() {
+            total += match beam {
+                Direction::N => (self.height - row) * rounds.count_ones() as usize,
+                Direction::S => (row + 1) * rounds.count_ones() as usize,
+                Direction::W => (0..self.width)
+                    .filter(|column| rounds & (1 << column) != 0)
+                    .map(|column| self.width - column)
+                    .sum(),
+                Direction::E => (0..self.width)
+                    .filter(|column| rounds & (1 << column) != 0)
+                    .map(|column| column + 1)
+                    .sum(),
+            };
         }
+        total
     }
-    return total;
-}
 
-fn total_load(rock_map: &RockMap) -> isize {
-    (0..rock_map.width)
-        .map(|col| column_sum(col, rock_map))
-        .sum()
+    fn render(&self) -> String {
+        let mut output = String::new();
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let bit = 1 << column;
+                output.push(if self.rounds[row] & bit != 0 {
+                    'O'
+                } else if self.cubes[row] & bit != 0 {
+                    '#'
+                } else {
+                    '.'
+                });
+            }
+            output.push('\n');
+        }
+        output
+    }
 }